@@ -0,0 +1,278 @@
+/// Which adapter and device features are used, `pussy info` lists what is available.
+#[derive(Clone, Default)]
+pub struct AdapterOptions {
+    /// `None` reads the `WGPU_BACKEND` variable, or uses all backends.
+    pub backends: Option<wgpu::Backends>,
+    /// `None` lets wgpu pick by `power_preference`.
+    pub adapter: Option<AdapterSelector>,
+    pub power_preference: wgpu::PowerPreference,
+    /// Only a software adapter (like llvmpipe or WARP) is accepted.
+    pub software: bool,
+    pub features: Vec<OptionalFeature>,
+    pub limits: LimitsPreset,
+}
+
+#[derive(Clone)]
+pub enum AdapterSelector {
+    Index(usize),
+    /// Case insensitive part of the adapter name.
+    Name(String),
+}
+
+impl std::str::FromStr for AdapterSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse() {
+            Ok(i) => Self::Index(i),
+            Err(_) => Self::Name(s.to_lowercase()),
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OptionalFeature {
+    TimestampQuery,
+    Float32Filterable,
+    PushConstants,
+}
+
+impl OptionalFeature {
+    fn feature(&self) -> wgpu::Features {
+        match self {
+            Self::TimestampQuery => wgpu::Features::TIMESTAMP_QUERY,
+            // lets adapters that can filter rgba32f textures do so
+            Self::Float32Filterable => wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+            Self::PushConstants => wgpu::Features::PUSH_CONSTANTS,
+        }
+    }
+
+    // the adapter specific format features don't make float formats filterable everywhere
+    fn is_supported(&self, adapter: &wgpu::Adapter) -> bool {
+        adapter.features().contains(self.feature())
+            && (*self != Self::Float32Filterable || filters_float32(adapter))
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::TimestampQuery => "timestamp-query",
+            Self::Float32Filterable => "float32-filterable",
+            Self::PushConstants => "push-constants",
+        }
+    }
+}
+
+impl std::str::FromStr for OptionalFeature {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "timestamp-query" => Ok(Self::TimestampQuery),
+            "float32-filterable" => Ok(Self::Float32Filterable),
+            "push-constants" => Ok(Self::PushConstants),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported feature `{s}`, expected timestamp-query, float32-filterable or push-constants"
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum LimitsPreset {
+    #[default]
+    Default,
+    /// For GLES3 and WebGL2 level adapters.
+    Downlevel,
+    /// Everything the adapter supports.
+    Max,
+}
+
+impl std::str::FromStr for LimitsPreset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "downlevel" => Ok(Self::Downlevel),
+            "max" => Ok(Self::Max),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported limits `{s}`, expected default, downlevel or max"
+            )),
+        }
+    }
+}
+
+/// Comma separated backend names.
+pub fn parse_backends(s: &str) -> anyhow::Result<wgpu::Backends> {
+    s.split(',')
+        .try_fold(wgpu::Backends::empty(), |backends, name| {
+            let backend = match name.trim() {
+                "vulkan" => wgpu::Backends::VULKAN,
+                "gl" => wgpu::Backends::GL,
+                "metal" => wgpu::Backends::METAL,
+                "dx12" => wgpu::Backends::DX12,
+                "dx11" => wgpu::Backends::DX11,
+                "all" => wgpu::Backends::all(),
+                _ => anyhow::bail!(
+                    "Unsupported backend `{name}`, expected vulkan, gl, metal, dx12, dx11 or all"
+                ),
+            };
+            Ok(backends | backend)
+        })
+}
+
+impl AdapterOptions {
+    pub fn backends(&self) -> wgpu::Backends {
+        self.backends
+            .or_else(wgpu::util::backend_bits_from_env)
+            .unwrap_or(wgpu::Backends::all())
+    }
+
+    pub fn instance(&self) -> wgpu::Instance {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: self.backends(),
+            ..Default::default()
+        })
+    }
+
+    /// With a `surface` the adapter has to be able to present to it.
+    pub async fn request_adapter(
+        &self,
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface>,
+    ) -> anyhow::Result<wgpu::Adapter> {
+        let Some(ref selector) = self.adapter else {
+            return instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: self.power_preference,
+                    force_fallback_adapter: self.software,
+                    compatible_surface: surface,
+                })
+                .await
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "No suitable adapter found, `pussy info` lists the available ones"
+                    )
+                });
+        };
+        let is_software = |a: &wgpu::Adapter| a.get_info().device_type == wgpu::DeviceType::Cpu;
+        // indices are the ones `print_adapters` shows, whatever `software` is
+        let mut adapters = instance.enumerate_adapters(self.backends());
+        let adapter = match selector {
+            AdapterSelector::Index(i) => adapters.nth(*i),
+            AdapterSelector::Name(name) => adapters.find(|a| {
+                (!self.software || is_software(a))
+                    && a.get_info().name.to_lowercase().contains(name)
+            }),
+        };
+        let adapter = adapter.ok_or_else(|| {
+            anyhow::anyhow!("Adapter not found, `pussy info` lists the available ones")
+        })?;
+        if self.software && !is_software(&adapter) {
+            anyhow::bail!(
+                "Adapter `{}` is not a software adapter",
+                adapter.get_info().name
+            );
+        }
+        if let Some(surface) = surface {
+            if !adapter.is_surface_supported(surface) {
+                anyhow::bail!(
+                    "Adapter `{}` can't present to the window",
+                    adapter.get_info().name
+                );
+            }
+        }
+        Ok(adapter)
+    }
+
+    /// Optional features the adapter lacks are skipped with a warning,
+    /// limits it can't reach are an error.
+    pub fn device_descriptor(
+        &self,
+        adapter: &wgpu::Adapter,
+    ) -> anyhow::Result<wgpu::DeviceDescriptor<'static>> {
+        // for `crate::timing::GpuTimer`, if it's there
+        let mut features = adapter.features() & wgpu::Features::TIMESTAMP_QUERY;
+        for feature in &self.features {
+            if feature.is_supported(adapter) {
+                features |= feature.feature();
+            } else {
+                log::warn!("`{}` is not supported by the adapter", feature.name());
+            }
+        }
+
+        let supported = adapter.limits();
+        let mut limits = match self.limits {
+            LimitsPreset::Default => wgpu::Limits::default(),
+            LimitsPreset::Downlevel => wgpu::Limits::downlevel_defaults(),
+            LimitsPreset::Max => supported.clone(),
+        };
+        if features.contains(wgpu::Features::PUSH_CONSTANTS) {
+            limits.max_push_constant_size = supported.max_push_constant_size;
+        }
+        let mut exceeded = Vec::new();
+        limits.check_limits_with_fail_fn(&supported, false, |name, requested, allowed| {
+            exceeded.push(format!("{name} {requested} (at most {allowed})"))
+        });
+        if !exceeded.is_empty() {
+            anyhow::bail!(
+                "The adapter doesn't support the requested limits: {}, try `--limits downlevel`",
+                exceeded.join(", ")
+            );
+        }
+
+        Ok(wgpu::DeviceDescriptor {
+            label: None,
+            features,
+            limits,
+        })
+    }
+}
+
+/// Whether the 32 bit float formats of inputs are filterable with the adapter's own format
+/// features, see [`OptionalFeature::Float32Filterable`].
+pub fn filters_float32(adapter: &wgpu::Adapter) -> bool {
+    [
+        wgpu::TextureFormat::R32Float,
+        wgpu::TextureFormat::Rgba32Float,
+    ]
+    .iter()
+    .all(|format| {
+        adapter
+            .get_texture_format_features(*format)
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+    })
+}
+
+/// Prints the adapters of the selected backends with their indices for `--adapter`.
+pub fn print_adapters(options: &AdapterOptions) {
+    let instance = options.instance();
+    let mut empty = true;
+    for (i, adapter) in instance.enumerate_adapters(options.backends()).enumerate() {
+        empty = false;
+        let info = adapter.get_info();
+        let limits = adapter.limits();
+        println!(
+            "{i}: {} ({:?}, {:?})",
+            info.name, info.backend, info.device_type
+        );
+        if !info.driver.is_empty() {
+            println!("   driver: {} {}", info.driver, info.driver_info);
+        }
+        for feature in [
+            OptionalFeature::TimestampQuery,
+            OptionalFeature::Float32Filterable,
+            OptionalFeature::PushConstants,
+        ] {
+            println!("   {}: {}", feature.name(), feature.is_supported(&adapter));
+        }
+        println!(
+            "   max texture size: {}, max push constant size: {}",
+            limits.max_texture_dimension_2d, limits.max_push_constant_size
+        );
+    }
+    if empty {
+        println!("No adapters found");
+    }
+}
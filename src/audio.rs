@@ -0,0 +1,243 @@
+use anyhow::Context;
+use std::{
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    time::Instant,
+};
+
+pub const SAMPLE_RATE: u32 = 44100;
+/// Same layout as ShaderToy's audio inputs:
+/// the first row holds the spectrum and the second one the waveform.
+pub const TEXTURE_WIDTH: u32 = 512;
+pub const TEXTURE_HEIGHT: u32 = 2;
+pub const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+const FFT_SIZE: usize = 2048;
+// decibel range mapped onto 0..1, the defaults of the WebAudio AnalyserNode
+const MIN_DB: f32 = -100.0;
+const MAX_DB: f32 = -30.0;
+// how far the player can drift away from `Time` before it gets restarted
+const MAX_DRIFT_SECS: f32 = 0.25;
+
+pub struct Audio {
+    pub path: PathBuf,
+    samples: Vec<f32>,
+    player: Option<Player>,
+    // set when ffplay couldn't be spawned, so it isn't retried every frame
+    playback_failed: bool,
+}
+
+struct Player {
+    process: Child,
+    started: Instant,
+    offset: f32,
+}
+
+impl Audio {
+    #[rustfmt::skip]
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let rate = format!("{SAMPLE_RATE}");
+        let decoded = Command::new("ffmpeg")
+            .args(["-v", "error", "-nostdin"])
+            .arg("-i")
+            .arg(path)
+            .args([
+                // mono 32-bit float samples
+                "-f", "f32le",
+                "-ac", "1",
+                "-ar", &rate,
+                "-vn",
+                "-",
+            ])
+            .stdin(Stdio::null())
+            .output()
+            .context("Failed to spawn ffmpeg")?;
+
+        if !decoded.status.success() {
+            anyhow::bail!(
+                "{path:?}: {err}",
+                err = String::from_utf8_lossy(&decoded.stderr).trim()
+            );
+        }
+
+        let samples = decoded.stdout
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect::<Vec<f32>>();
+
+        log::info!(
+            "Opened audio {path:?}: {secs:.2}s",
+            secs = samples.len() as f32 / SAMPLE_RATE as f32
+        );
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            samples,
+            player: None,
+            playback_failed: false,
+        })
+    }
+
+    /// Analyses the samples right before `time` into the `TEXTURE_FORMAT` texture data.
+    /// Unlike ShaderToy there is no smoothing between frames,
+    /// so the result only depends on `time` and offline renders are reproducible.
+    pub fn texture_at(&self, time: f32) -> Vec<u8> {
+        let end = (time.max(0.0) * SAMPLE_RATE as f32) as i64;
+        // silence before the start and after the end of the track
+        let window = (end - FFT_SIZE as i64..end)
+            .map(|i| {
+                usize::try_from(i)
+                    .ok()
+                    .and_then(|i| self.samples.get(i))
+                    .copied()
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<f32>>();
+
+        let mut spectrum = window
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                // Hann window
+                let w = 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / FFT_SIZE as f32).cos();
+                [s * w, 0.0]
+            })
+            .collect::<Vec<[f32; 2]>>();
+        fft(&mut spectrum);
+
+        // like ShaderToy's the row holds the first bins, up to a quarter of the sample rate
+        let fft_row = spectrum[..TEXTURE_WIDTH as usize].iter().map(|[re, im]| {
+            let magnitude = (re * re + im * im).sqrt() / FFT_SIZE as f32;
+            let db = 20.0 * magnitude.max(f32::MIN_POSITIVE).log10();
+            to_unorm((db - MIN_DB) / (MAX_DB - MIN_DB))
+        });
+        let wave_row = window[FFT_SIZE - TEXTURE_WIDTH as usize..]
+            .iter()
+            .map(|s| to_unorm(s * 0.5 + 0.5));
+
+        fft_row.chain(wave_row).collect()
+    }
+
+    /// Keeps the audio output in sync with `time`,
+    /// restarting the player after seeks and stopping it while paused.
+    pub fn sync_playback(&mut self, time: f32, paused: bool) {
+        if paused || self.playback_failed {
+            self.player = None;
+            return;
+        }
+
+        let in_sync = self.player.as_ref().is_some_and(|p| {
+            (p.offset + p.started.elapsed().as_secs_f32() - time).abs() < MAX_DRIFT_SECS
+        });
+        if !in_sync {
+            self.player = match Player::spawn(&self.path, time) {
+                Ok(p) => Some(p),
+                Err(e) => {
+                    log::error!("{e}, audio playback is disabled");
+                    self.playback_failed = true;
+                    None
+                }
+            };
+        }
+    }
+}
+
+impl Player {
+    #[rustfmt::skip]
+    fn spawn(path: &Path, offset: f32) -> anyhow::Result<Self> {
+        let start = format!("{offset}");
+        let process = Command::new("ffplay")
+            .args([
+                "-v", "error",
+                "-nodisp",
+                "-autoexit",
+                "-ss", &start,
+            ])
+            .arg(path)
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => anyhow::anyhow!(
+                    "ffplay was not found, it comes with FFmpeg and has to be in PATH"
+                ),
+                _ => anyhow::anyhow!("Failed to spawn ffplay: {e}"),
+            })?;
+
+        Ok(Self {
+            process,
+            started: Instant::now(),
+            offset,
+        })
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+fn to_unorm(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// In-place iterative radix-2 FFT, `data.len()` has to be a power of two.
+fn fft(data: &mut [[f32; 2]]) {
+    let n = data.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -std::f32::consts::TAU / len as f32;
+        for chunk in data.chunks_exact_mut(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let [ore, oim] = chunk[k + len / 2];
+                let t = [ore * cos - oim * sin, ore * sin + oim * cos];
+                let [ere, eim] = chunk[k];
+                chunk[k] = [ere + t[0], eim + t[1]];
+                chunk[k + len / 2] = [ere - t[0], eim - t[1]];
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tone_peaks_at_its_bin() {
+        let bin = 300;
+        let freq = bin as f32 * SAMPLE_RATE as f32 / FFT_SIZE as f32;
+        // quiet enough to stay below `MAX_DB`
+        let samples = (0..SAMPLE_RATE)
+            .map(|i| 0.001 * (std::f32::consts::TAU * freq * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect();
+        let audio = Audio {
+            path: PathBuf::new(),
+            samples,
+            player: None,
+            playback_failed: false,
+        };
+
+        let texture = audio.texture_at(0.5);
+        let fft_row = &texture[..TEXTURE_WIDTH as usize];
+        let max = fft_row.iter().max().unwrap();
+        assert_eq!(fft_row.iter().position(|v| v == max), Some(bin));
+    }
+}
@@ -13,9 +13,12 @@ macro_rules! shader_bindings_impl {
         }
 
         impl $struct_name {
+            /// The WGSL declaration of each binding, in order.
+            const DECLS: &'static [&'static str] = &[$( $decl ),+];
+
             $vis fn new(device: &wgpu::Device) -> Self {
                 Self {
-                    $( $field: BufferBinding::new(device, $decl), )+
+                    $( $field: <$type_of>::new(device), )+
                 }
             }
 
@@ -30,21 +33,22 @@ macro_rules! shader_bindings_impl {
 }
 
 trait Binding {
-    fn bind(&self) -> wgpu::BindingResource;
-    fn stage(&self, queue: &wgpu::Queue);
-    fn as_wgsl_str(&self) -> &str;
+    fn bind(&self) -> wgpu::BindingResource<'_>;
+    fn binding_type(&self) -> wgpu::BindingType;
+
+    fn visibility(&self) -> wgpu::ShaderStages {
+        wgpu::ShaderStages::all()
+    }
 }
 
 pub struct BufferBinding<T> {
     data: T,
-    decl: &'static str,
     buffer: wgpu::Buffer,
 }
 
 impl<T: bytemuck::Pod + Default> BufferBinding<T> {
-    fn new(device: &wgpu::Device, decl: &'static str) -> Self {
+    fn new(device: &wgpu::Device) -> Self {
         Self {
-            decl,
             data: T::default(),
             buffer: device.create_buffer(&wgpu::BufferDescriptor {
                 label: None,
@@ -59,20 +63,387 @@ impl<T: bytemuck::Pod + Default> BufferBinding<T> {
         self.data = new;
         self.stage(q);
     }
+
+    fn stage(&self, queue: &wgpu::Queue) {
+        let data = bytemuck::bytes_of(&self.data).to_vec();
+        queue.write_buffer(&self.buffer, 0, &data);
+    }
 }
 
 impl<T: bytemuck::Pod> Binding for BufferBinding<T> {
-    fn bind(&self) -> wgpu::BindingResource {
+    fn bind(&self) -> wgpu::BindingResource<'_> {
         wgpu::BindingResource::Buffer(wgpu::Buffer::as_entire_buffer_binding(&self.buffer))
     }
 
-    fn stage(&self, queue: &wgpu::Queue) {
-        let data = bytemuck::bytes_of(&self.data).to_vec();
-        queue.write_buffer(&self.buffer, 0, &data);
+    fn binding_type(&self) -> wgpu::BindingType {
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        }
     }
+}
+
+/// Values declared with `--uniform`, the members of the `CustomUniforms` struct.
+/// Nothing is declared in WGSL without any of them.
+pub struct UniformsBinding {
+    // name, offset in floats and value of each member
+    fields: Vec<(String, usize, Vec<f32>)>,
+    buffer: wgpu::Buffer,
+}
 
-    fn as_wgsl_str(&self) -> &str {
-        self.decl
+impl UniformsBinding {
+    fn new(device: &wgpu::Device) -> Self {
+        Self {
+            fields: Vec::new(),
+            buffer: Self::create_buffer(device, 4),
+        }
+    }
+
+    /// Lays out the members, values of members that keep their name and size are kept.
+    pub fn declare(
+        &mut self,
+        device: &wgpu::Device,
+        q: &wgpu::Queue,
+        uniforms: &[(String, Vec<f32>)],
+    ) {
+        let mut offset = 0usize;
+        let fields = uniforms
+            .iter()
+            .map(|(name, default)| {
+                // WGSL alignment of f32, vec2 and vec3/vec4
+                let align = match default.len() {
+                    1 => 1,
+                    2 => 2,
+                    _ => 4,
+                };
+                offset = offset.next_multiple_of(align);
+                let value = match self.get(name) {
+                    Some(value) if value.len() == default.len() => value.to_vec(),
+                    _ => default.clone(),
+                };
+                let field = (name.clone(), offset, value);
+                offset += default.len();
+                field
+            })
+            .collect();
+        self.fields = fields;
+        self.buffer = Self::create_buffer(device, offset.next_multiple_of(4).max(4));
+        self.stage(q);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[f32]> {
+        self.fields
+            .iter()
+            .find(|(n, ..)| n == name)
+            .map(|(_, _, value)| value.as_slice())
+    }
+
+    pub fn set(&mut self, q: &wgpu::Queue, name: &str, new: &[f32]) -> anyhow::Result<()> {
+        let Some((_, _, value)) = self.fields.iter_mut().find(|(n, ..)| n == name) else {
+            anyhow::bail!("Unknown uniform `{name}`, declare it with `--uniform {name}=<VALUES>`");
+        };
+        if value.len() != new.len() {
+            anyhow::bail!("`{name}` has {} components, got {}", value.len(), new.len());
+        }
+        value.copy_from_slice(new);
+        self.stage(q);
+        Ok(())
+    }
+
+    /// Names and values in the order they were declared.
+    pub fn values(&self) -> impl Iterator<Item = (&str, &[f32])> {
+        self.fields
+            .iter()
+            .map(|(name, _, value)| (name.as_str(), value.as_slice()))
+    }
+
+    fn stage(&self, q: &wgpu::Queue) {
+        let mut data = vec![0f32; (self.buffer.size() / 4) as usize];
+        for (_, offset, value) in &self.fields {
+            data[*offset..*offset + value.len()].copy_from_slice(value);
+        }
+        q.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&data));
+    }
+
+    fn create_buffer(device: &wgpu::Device, floats: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (floats * std::mem::size_of::<f32>()) as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
+
+impl Binding for UniformsBinding {
+    fn bind(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::Buffer::as_entire_buffer_binding(&self.buffer))
+    }
+
+    fn binding_type(&self) -> wgpu::BindingType {
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        }
+    }
+}
+
+/// Shape of the texture behind a [`TextureBinding`].
+pub trait TextureKind {
+    const DIMENSION: wgpu::TextureDimension;
+    const VIEW_DIMENSION: wgpu::TextureViewDimension;
+    // layers of the placeholder texture
+    const LAYERS: u32;
+}
+
+pub struct Flat;
+pub struct Cube;
+pub struct Volume;
+
+impl TextureKind for Flat {
+    const DIMENSION: wgpu::TextureDimension = wgpu::TextureDimension::D2;
+    const VIEW_DIMENSION: wgpu::TextureViewDimension = wgpu::TextureViewDimension::D2;
+    const LAYERS: u32 = 1;
+}
+
+impl TextureKind for Cube {
+    const DIMENSION: wgpu::TextureDimension = wgpu::TextureDimension::D2;
+    const VIEW_DIMENSION: wgpu::TextureViewDimension = wgpu::TextureViewDimension::Cube;
+    const LAYERS: u32 = 6;
+}
+
+impl TextureKind for Volume {
+    const DIMENSION: wgpu::TextureDimension = wgpu::TextureDimension::D3;
+    const VIEW_DIMENSION: wgpu::TextureViewDimension = wgpu::TextureViewDimension::D3;
+    const LAYERS: u32 = 1;
+}
+
+pub struct TextureBinding<K = Flat> {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    kind: std::marker::PhantomData<K>,
+}
+
+impl<K: TextureKind> TextureBinding<K> {
+    fn new(device: &wgpu::Device) -> Self {
+        // 1x1 placeholder until some input is attached to the binding
+        let size = wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: K::LAYERS,
+        };
+        let texture = Self::create_texture(device, size, wgpu::TextureFormat::Rgba8UnormSrgb);
+        let view = Self::create_view(&texture);
+
+        Self {
+            texture,
+            view,
+            kind: std::marker::PhantomData,
+        }
+    }
+
+    /// Uploads tightly packed pixels (layers or slices one after another),
+    /// recreating the texture if the size or format has changed.
+    /// `format` has to be filterable, since all textures share the same `Sampler`,
+    /// 32 bit floats only are with `WgpuContext::float32_filterable`.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        q: &wgpu::Queue,
+        size: wgpu::Extent3d,
+        format: wgpu::TextureFormat,
+        data: &[u8],
+    ) {
+        if self.texture.size() != size || self.texture.format() != format {
+            self.texture = Self::create_texture(device, size, format);
+            self.view = Self::create_view(&self.texture);
+        }
+
+        q.write_texture(
+            self.texture.as_image_copy(),
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.width * format.block_size(None).unwrap_or(4)),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+    }
+
+    fn create_texture(
+        device: &wgpu::Device,
+        size: wgpu::Extent3d,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: K::DIMENSION,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+
+    fn create_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(K::VIEW_DIMENSION),
+            ..Default::default()
+        })
+    }
+}
+
+impl<K: TextureKind> Binding for TextureBinding<K> {
+    fn bind(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::TextureView(&self.view)
+    }
+
+    fn binding_type(&self) -> wgpu::BindingType {
+        wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: K::VIEW_DIMENSION,
+            multisampled: false,
+        }
+    }
+}
+
+/// Buffer that shaders can write into and that can be read back after the frame.
+pub struct StorageBinding<T> {
+    buffer: wgpu::Buffer,
+    data: std::marker::PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> StorageBinding<T> {
+    fn new(device: &wgpu::Device) -> Self {
+        Self {
+            // zeroed by wgpu
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: std::mem::size_of::<T>() as u64,
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            data: std::marker::PhantomData,
+        }
+    }
+
+    pub fn update(&self, q: &wgpu::Queue, new: &T) {
+        q.write_buffer(&self.buffer, 0, bytemuck::bytes_of(new));
+    }
+
+    /// Blocks until all submitted frames are done.
+    pub fn read(&self, device: &wgpu::Device, q: &wgpu::Queue) -> T {
+        let size = std::mem::size_of::<T>() as u64;
+        let staging = crate::ctx::create_buffer(device, size);
+        let mut encoder = crate::ctx::create_encoder(device);
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &staging, 0, size);
+        let submission_idx = q.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            if let Err(e) = result {
+                log::error!("Failed to map storage buffer: {e}");
+            }
+        });
+        device.poll(wgpu::Maintain::WaitForSubmissionIndex(submission_idx));
+        let data = bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        staging.unmap();
+
+        data
+    }
+}
+
+impl<T> Binding for StorageBinding<T> {
+    fn bind(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::Buffer::as_entire_buffer_binding(&self.buffer))
+    }
+
+    fn binding_type(&self) -> wgpu::BindingType {
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        }
+    }
+
+    // writable storage in the vertex stage needs an extra feature
+    fn visibility(&self) -> wgpu::ShaderStages {
+        wgpu::ShaderStages::FRAGMENT
+    }
+}
+
+pub const DEBUG_ENTRIES: usize = 64;
+
+/// Value passed to `debug_print` in WGSL.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugEntry {
+    pub id: u32,
+    /// Number of used components of `value`.
+    pub len: u32,
+    _pad: [u32; 2],
+    pub value: [f32; 4],
+}
+
+/// Layout of the `DebugOutput` buffer, see `crate::pp::generate_debug_print`.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugOutput {
+    pub pixel: [u32; 2],
+    pub enabled: u32,
+    /// Can be greater than `DEBUG_ENTRIES`, extra values are dropped.
+    pub count: u32,
+    pub entries: [DebugEntry; DEBUG_ENTRIES],
+}
+
+impl DebugOutput {
+    /// Only `debug_print` calls made for `pixel` are recorded, none if it's `None`.
+    pub fn select(pixel: Option<[u32; 2]>) -> Self {
+        Self {
+            pixel: pixel.unwrap_or_default(),
+            enabled: pixel.is_some() as _,
+            ..bytemuck::Zeroable::zeroed()
+        }
+    }
+
+    pub fn entries(&self) -> &[DebugEntry] {
+        &self.entries[..(self.count as usize).min(DEBUG_ENTRIES)]
+    }
+}
+
+pub struct SamplerBinding {
+    sampler: wgpu::Sampler,
+}
+
+impl SamplerBinding {
+    fn new(device: &wgpu::Device) -> Self {
+        Self {
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::Repeat,
+                address_mode_v: wgpu::AddressMode::Repeat,
+                address_mode_w: wgpu::AddressMode::Repeat,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl Binding for SamplerBinding {
+    fn bind(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Sampler(&self.sampler)
+    }
+
+    fn binding_type(&self) -> wgpu::BindingType {
+        wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
     }
 }
 
@@ -81,58 +452,189 @@ shader_bindings_impl! {
         pub time: BufferBinding<f32> = "var<uniform> Time: f32",
         pub resolution: BufferBinding<[f32; 2]> = "var<uniform> Resolution: vec2<f32>",
         pub mouse: BufferBinding<[f32; 2]> = "var<uniform> Mouse: vec2<f32>",
+        pub model_matrix: BufferBinding<[[f32; 4]; 4]> = "var<uniform> ModelMatrix: mat4x4<f32>",
+        // orbit camera, see `crate::camera`
+        pub camera_position: BufferBinding<[f32; 3]> = "var<uniform> CameraPosition: vec3<f32>",
+        pub camera_view: BufferBinding<[[f32; 4]; 4]> = "var<uniform> CameraView: mat4x4<f32>",
+        pub camera_projection: BufferBinding<[[f32; 4]; 4]> =
+            "var<uniform> CameraProjection: mat4x4<f32>",
+        pub camera_inverse_view: BufferBinding<[[f32; 4]; 4]> =
+            "var<uniform> CameraInverseView: mat4x4<f32>",
+        pub camera_inverse_projection: BufferBinding<[[f32; 4]; 4]> =
+            "var<uniform> CameraInverseProjection: mat4x4<f32>",
+        // declared with `--uniform`
+        pub uniforms: UniformsBinding = UNIFORMS_DECL,
+        pub sampler: SamplerBinding = "var Sampler: sampler",
+        // inputs attached with `--channel<N>`, see `crate::input`
+        pub channel0: TextureBinding = "var Channel0: texture_2d<f32>",
+        pub channel1: TextureBinding = "var Channel1: texture_2d<f32>",
+        pub channel2: TextureBinding = "var Channel2: texture_2d<f32>",
+        pub channel3: TextureBinding = "var Channel3: texture_2d<f32>",
+        // static textures attached with `--cube<N>` and `--volume<N>`, see `crate::texture`
+        pub cube0: TextureBinding<Cube> = "var Cube0: texture_cube<f32>",
+        pub cube1: TextureBinding<Cube> = "var Cube1: texture_cube<f32>",
+        pub volume0: TextureBinding<Volume> = "var Volume0: texture_3d<f32>",
+        pub volume1: TextureBinding<Volume> = "var Volume1: texture_3d<f32>",
+        // written by `debug_print`, only bound for shaders that use it
+        pub debug: StorageBinding<DebugOutput> = DEBUG_DECL,
+    }
+
+    pub fn channel_mut(&mut self, idx: usize) -> Option<&mut TextureBinding> {
+        match idx {
+            0 => Some(&mut self.channel0),
+            1 => Some(&mut self.channel1),
+            2 => Some(&mut self.channel2),
+            3 => Some(&mut self.channel3),
+            _ => None,
+        }
+    }
+
+    pub fn cube_mut(&mut self, idx: usize) -> Option<&mut TextureBinding<Cube>> {
+        match idx {
+            0 => Some(&mut self.cube0),
+            1 => Some(&mut self.cube1),
+            _ => None,
+        }
+    }
+
+    pub fn volume_mut(&mut self, idx: usize) -> Option<&mut TextureBinding<Volume>> {
+        match idx {
+            0 => Some(&mut self.volume0),
+            1 => Some(&mut self.volume1),
+            _ => None,
+        }
     }
 
-    pub fn create_bind_group_layout(&self, device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    // with the indices of the declarations, the debug buffer is left out without `debug`
+    fn entries(&self, debug: bool) -> Vec<(u32, &dyn Binding)> {
+        self.to_vec()
+            .into_iter()
+            .zip(Self::DECLS)
+            .enumerate()
+            .filter(|(_, (_, decl))| debug || **decl != DEBUG_DECL)
+            .map(|(i, (b, _))| (i as u32, b))
+            .collect()
+    }
+
+    // with `debug` the layout has the `DebugOutput` buffer, see `ShaderSource::debug`
+    pub fn create_bind_group_layout(
+        &self,
+        device: &wgpu::Device,
+        debug: bool,
+    ) -> wgpu::BindGroupLayout {
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
             entries: &self
-                .to_vec()
-                .iter()
-                .enumerate()
-                .map(|(i, _)| wgpu::BindGroupLayoutEntry {
-                    binding: i as _,
-                    visibility: wgpu::ShaderStages::all(),
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
+                .entries(debug)
+                .into_iter()
+                .map(|(i, b)| wgpu::BindGroupLayoutEntry {
+                    binding: i,
+                    visibility: b.visibility(),
+                    ty: b.binding_type(),
                     count: None,
                 })
                 .collect::<Vec<_>>(),
         })
     }
 
-    pub fn create_bind_group(&self, device: &wgpu::Device) -> wgpu::BindGroup {
-        let layout = &self.create_bind_group_layout(device);
+    pub fn create_bind_group(&self, device: &wgpu::Device, debug: bool) -> wgpu::BindGroup {
+        let layout = &self.create_bind_group_layout(device, debug);
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout,
             entries: &self
-                .to_vec()
-                .iter()
-                .enumerate()
+                .entries(debug)
+                .into_iter()
                 .map(|(i, b)| wgpu::BindGroupEntry {
-                    binding: i as _,
+                    binding: i,
                     resource: b.bind(),
                 })
                 .collect::<Vec<_>>(),
         })
     }
 
-    pub fn as_wgsl_string(&self) -> String {
-        self.to_vec()
+    // the declarations with the members of `Uniforms` as they are declared now
+    pub fn decls(&self) -> BindingDecls {
+        BindingDecls::new(self.uniforms.values().map(|(name, value)| (name, value.len())))
+    }
+}
+
+/// Names of bindings from before the orbit camera and the bindings they stand for,
+/// WGSL shaders get them as defines.
+pub const BINDING_ALIASES: [(&str, &str); 2] = [
+    ("ViewMatrix", "CameraView"),
+    ("ProjectionMatrix", "CameraProjection"),
+];
+
+const UNIFORMS_DECL: &str = "var<uniform> Uniforms: CustomUniforms";
+const DEBUG_DECL: &str = "var<storage, read_write> DebugOutput: DebugBuffer";
+
+/// The WGSL declarations of [`ShaderBindings`], which don't need a device,
+/// so shaders can be validated and exported without one.
+pub struct BindingDecls {
+    // name and number of components of each member of `Uniforms`
+    uniforms: Vec<(String, usize)>,
+}
+
+impl BindingDecls {
+    pub fn new<'a>(uniforms: impl IntoIterator<Item = (&'a str, usize)>) -> Self {
+        Self {
+            uniforms: uniforms
+                .into_iter()
+                .map(|(name, len)| (name.to_owned(), len))
+                .collect(),
+        }
+    }
+
+    // `Uniforms` isn't declared without members, an empty struct isn't valid WGSL,
+    // `DebugOutput` only with `debug`
+    fn decls(&self, debug: bool) -> impl Iterator<Item = &'static str> + '_ {
+        ShaderBindings::DECLS.iter().map(move |decl| {
+            if (*decl == UNIFORMS_DECL && self.uniforms.is_empty())
+                || (*decl == DEBUG_DECL && !debug)
+            {
+                ""
+            } else {
+                decl
+            }
+        })
+    }
+
+    /// With `debug` the `DebugOutput` buffer is declared, its struct isn't.
+    pub fn as_wgsl_string(&self, debug: bool) -> String {
+        // the struct of the custom uniforms comes first
+        let mut wgsl = String::new();
+        if !self.uniforms.is_empty() {
+            let members = self
+                .uniforms
+                .iter()
+                .map(|(name, len)| match len {
+                    1 => format!("    {name}: f32,\n"),
+                    n => format!("    {name}: vec{n}<f32>,\n"),
+                })
+                .collect::<String>();
+            wgsl += &format!("struct CustomUniforms {{\n{members}}};\n");
+        }
+        for (i, decl) in self.decls(debug).enumerate() {
+            if !decl.is_empty() {
+                wgsl += &format!("@group(0) @binding({i}) {decl};\n");
+            }
+        }
+        wgsl
+    }
+
+    // binding of the global declared as `name`, for shaders that don't get the declarations.
+    pub fn index_of(&self, name: &str) -> Option<u32> {
+        let name = BINDING_ALIASES
             .iter()
-            .enumerate()
-            .map(|(i, b)| {
-                let decl = b.as_wgsl_str();
-                if decl.is_empty() {
-                    String::new()
-                } else {
-                    format!("@group(0) @binding({i}) {decl};\n")
-                }
+            .find(|(alias, _)| *alias == name)
+            .map_or(name, |(_, name)| name);
+        self.decls(true)
+            .position(|decl| {
+                // `var<uniform> Time: f32`
+                let decl = decl.split(':').next().unwrap_or_default();
+                decl.split_whitespace().last() == Some(name)
             })
-            .collect()
+            .map(|i| i as u32)
     }
 }
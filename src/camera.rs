@@ -0,0 +1,145 @@
+use glam::{Mat4, Vec3};
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode};
+
+// degrees per pixel of mouse drag
+const ORBIT_SPEED: f32 = 0.3;
+// distances per second, scaled by the distance to the target
+const FLY_SPEED: f32 = 1.0;
+const NEAR: f32 = 0.1;
+const FAR: f32 = 100.0;
+
+/// Orbit camera around `target`, angles are in degrees.
+#[derive(Clone, Copy)]
+pub struct Camera {
+    pub target: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+    /// Vertical field of view.
+    pub fov: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            target: Vec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
+            distance: 3.0,
+            fov: 45.0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn position(&self) -> Vec3 {
+        let (yaw, pitch) = (self.yaw.to_radians(), self.pitch.to_radians());
+        self.target
+            + self.distance
+                * Vec3::new(
+                    pitch.cos() * yaw.sin(),
+                    pitch.sin(),
+                    pitch.cos() * yaw.cos(),
+                )
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at_rh(self.position(), self.target, Vec3::Y)
+    }
+
+    pub fn projection(&self, aspect: f32) -> Mat4 {
+        Mat4::perspective_rh(self.fov.to_radians(), aspect, NEAR, FAR)
+    }
+
+    fn orbit(&mut self, dx: f32, dy: f32) {
+        self.yaw = (self.yaw - dx * ORBIT_SPEED).rem_euclid(360.0);
+        // the view matrix flips over the poles
+        self.pitch = (self.pitch + dy * ORBIT_SPEED).clamp(-89.0, 89.0);
+    }
+
+    fn zoom(&mut self, steps: f32) {
+        self.distance = (self.distance * 0.9f32.powf(steps)).max(NEAR);
+    }
+
+    /// The camera as `--camera`, `--camera-target` and `--fov` arguments.
+    pub fn as_args(&self) -> String {
+        format!(
+            "--camera {},{},{} --camera-target {},{},{} --fov {}",
+            self.yaw,
+            self.pitch,
+            self.distance,
+            self.target.x,
+            self.target.y,
+            self.target.z,
+            self.fov
+        )
+    }
+}
+
+/// Drives a [`Camera`] with mouse drag (orbit), scroll (zoom) and WASD (moves the target).
+#[derive(Default)]
+pub struct CameraController {
+    pub camera: Camera,
+    dragging: bool,
+    cursor: Option<(f32, f32)>,
+    // forward, right
+    movement: [f32; 2],
+}
+
+impl CameraController {
+    pub fn new(camera: Camera) -> Self {
+        Self {
+            camera,
+            ..Default::default()
+        }
+    }
+
+    /// Returns `true` if the key is used by the camera.
+    pub fn key(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
+        let amount = match state {
+            ElementState::Pressed => 1.0,
+            ElementState::Released => 0.0,
+        };
+        match key {
+            VirtualKeyCode::W => self.movement[0] = amount,
+            VirtualKeyCode::S => self.movement[0] = -amount,
+            VirtualKeyCode::D => self.movement[1] = amount,
+            VirtualKeyCode::A => self.movement[1] = -amount,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn mouse_button(&mut self, button: MouseButton, state: ElementState) {
+        if button == MouseButton::Left {
+            self.dragging = state == ElementState::Pressed;
+        }
+    }
+
+    pub fn cursor_moved(&mut self, x: f32, y: f32) {
+        if let Some((last_x, last_y)) = self.cursor.replace((x, y)) {
+            if self.dragging {
+                self.camera.orbit(x - last_x, y - last_y);
+            }
+        }
+    }
+
+    pub fn scroll(&mut self, delta: MouseScrollDelta) {
+        self.camera.zoom(match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(p) => p.y as f32 / 50.0,
+        });
+    }
+
+    /// Moves the target with the held keys, `dt` is in seconds.
+    pub fn update(&mut self, dt: f32) {
+        let [forward, right] = self.movement;
+        if forward == 0.0 && right == 0.0 {
+            return;
+        }
+        let dir = (self.camera.target - self.camera.position()).normalize();
+        let side = dir.cross(Vec3::Y).normalize_or_zero();
+        let speed = FLY_SPEED * self.camera.distance * dt;
+        self.camera.target += (dir * forward + side * right) * speed;
+    }
+}
@@ -1,8 +1,13 @@
-use crate::utils::RawFrame;
+use crate::{target::TargetFormat, utils::RawFrame};
 use anyhow::Context;
-use image::{codecs::png::PngEncoder, ImageEncoder};
+use image::{
+    codecs::{hdr::HdrEncoder, openexr::OpenExrEncoder, png::PngEncoder},
+    ImageEncoder,
+};
 use std::{
-    io::Write,
+    ffi::OsString,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
     process::{Command, Stdio},
 };
 use winit::dpi::PhysicalSize;
@@ -22,23 +27,202 @@ pub fn save_raw_frame_as_png(frame: &[u8], size: &PhysicalSize<u32>) -> anyhow::
     Ok(out_name)
 }
 
+/// Like [`save_raw_frame_as_png`], into `path` instead of a file named after the time.
+pub fn write_png(frame: &[u8], size: &PhysicalSize<u32>, path: &Path) -> anyhow::Result<()> {
+    let target_file =
+        std::fs::File::create(path).with_context(|| format!("Failed to create {path:?}"))?;
+    PngEncoder::new(target_file)
+        .write_image(frame, size.width, size.height, image::ColorType::Rgba8)
+        .with_context(|| format!("Failed to save {path:?}"))
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum HdrFormat {
+    #[default]
+    Exr,
+    /// Radiance RGBE, drops the alpha channel.
+    Hdr,
+}
+
+impl std::str::FromStr for HdrFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exr" => Ok(Self::Exr),
+            "hdr" => Ok(Self::Hdr),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported HDR format `{s}`, expected exr or hdr"
+            )),
+        }
+    }
+}
+
+/// Saves a frame read from a floating point render target without any tone mapping.
+pub fn save_raw_frame_as_hdr(
+    frame: &[u8],
+    size: &PhysicalSize<u32>,
+    target_format: TargetFormat,
+    format: HdrFormat,
+) -> anyhow::Result<String> {
+    if frame.is_empty() {
+        return Err(anyhow::Error::msg("Data for HDR encoding is not provided"));
+    }
+    if !target_format.is_hdr() {
+        anyhow::bail!("Render target is not a floating point one");
+    }
+    let pixels = target_format.decode(frame);
+
+    let out_name = crate::utils::current_time_string()
+        + match format {
+            HdrFormat::Exr => ".exr",
+            HdrFormat::Hdr => ".hdr",
+        };
+    log::info!("Saving HDR image as {out_name}");
+    let target_file = BufWriter::new(
+        crate::utils::create_file_cwd(&out_name)
+            .context("Failed to create file for saving raw buffer as HDR image")?,
+    );
+    match format {
+        HdrFormat::Exr => OpenExrEncoder::new(target_file)
+            .write_image(
+                bytemuck::cast_slice(&pixels),
+                size.width,
+                size.height,
+                image::ColorType::Rgba32F,
+            )
+            .context("Failed to save raw frame as exr")?,
+        HdrFormat::Hdr => {
+            let pixels = pixels
+                .chunks_exact(4)
+                .map(|p| image::Rgb([p[0], p[1], p[2]]))
+                .collect::<Vec<_>>();
+            HdrEncoder::new(target_file)
+                .encode(&pixels, size.width as _, size.height as _)
+                .context("Failed to save raw frame as hdr")?
+        }
+    }
+
+    Ok(out_name)
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum VideoFormat {
+    #[default]
+    Mp4,
+    WebM,
+}
+
+impl VideoFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::WebM => "webm",
+        }
+    }
+
+    #[rustfmt::skip]
+    fn codec_args(&self) -> [&'static str; 4] {
+        match self {
+            Self::Mp4 => ["-c:v", "libx264", "-c:a", "aac"],
+            Self::WebM => ["-c:v", "libvpx-vp9", "-c:a", "libopus"],
+        }
+    }
+}
+
+impl std::str::FromStr for VideoFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mp4" => Ok(Self::Mp4),
+            "webm" => Ok(Self::WebM),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported video format `{s}`, expected mp4 or webm"
+            )),
+        }
+    }
+}
+
+/// Audio file that is muxed into saved videos.
+#[derive(Clone)]
+pub struct Soundtrack {
+    pub path: PathBuf,
+    /// Position in the audio file (in seconds) that corresponds to `Time` = 0,
+    /// negative values delay the start of the track.
+    pub offset: f32,
+}
+
+impl Soundtrack {
+    /// Input arguments for the part of the track played while `Time` goes through `start..start + length`.
+    fn input_args(&self, start: f32, length: f32) -> Vec<OsString> {
+        let position = start + self.offset;
+        let mut args = Vec::<OsString>::new();
+        let duration = if position >= 0.0 {
+            args.extend(["-ss".into(), format!("{position}").into()]);
+            length
+        } else {
+            // silence until the track begins
+            args.extend(["-itsoffset".into(), format!("{}", -position).into()]);
+            (length + position).max(0.0)
+        };
+        args.extend(["-t".into(), format!("{duration}").into()]);
+        args.extend(["-i".into(), self.path.clone().into_os_string()]);
+
+        args
+    }
+}
+
+/// Turns frames captured at the given times (in seconds since the first one)
+/// into frames at a constant `rate`, repeating or dropping frames where needed.
+pub fn resample_frames(frames: Vec<(f32, RawFrame)>, rate: u32) -> Vec<RawFrame> {
+    let Some(&(end, _)) = frames.last() else {
+        return Vec::new();
+    };
+    let count = (end.max(0.0) * rate as f32) as usize + 1;
+    let mut current = 0;
+    (0..count)
+        .map(|i| {
+            // the last frame captured before the output frame is due
+            let time = i as f32 / rate as f32;
+            while frames.get(current + 1).is_some_and(|(t, _)| *t <= time) {
+                current += 1;
+            }
+            frames[current].1.clone()
+        })
+        .collect()
+}
+
+/// `start` is the value of `Time` at the first frame,
+/// the soundtrack is trimmed to the time range covered by `frames`.
+/// Without `out` the file is named after the current time.
 #[rustfmt::skip]
-pub fn save_raw_frames_as_mp4(
+pub fn save_raw_frames_as_video(
     frames: Vec<RawFrame>,
     size: &PhysicalSize<u32>,
-    rate: u32
+    rate: u32,
+    format: VideoFormat,
+    start: f32,
+    soundtrack: Option<&Soundtrack>,
+    out: Option<&Path>,
 ) -> anyhow::Result<String> {
-    if frames.is_empty() {
-        return Err(anyhow::Error::msg("Data for MP4 encoding is not provided"));
+    if frames.is_empty() || rate == 0 {
+        return Err(anyhow::Error::msg("Data for video encoding is not provided"));
     }
-    let out_name = crate::utils::current_time_string() + ".mp4";
+    let out_name = match out {
+        Some(path) => path.display().to_string(),
+        None => crate::utils::current_time_string() + "." + format.extension(),
+    };
     log::info!("Saving video as {out_name}");
+    let length = frames.len() as f32 / rate as f32;
     let size = format!("{width}x{height}", width = size.width, height = size.height);
     let rate = format!("{rate}");
     let mut ffmpeg = Command::new("ffmpeg")
         .args([
             // overwrite file if it already exists
             "-y",
+            // only errors, they are reported if encoding fails
+            "-v", "error",
             // accept raw data from stdin
             "-f", "rawvideo",
             "-pix_fmt", "rgba",
@@ -49,11 +233,12 @@ pub fn save_raw_frames_as_mp4(
             "-an",
             // get the data from stdin
             "-i", "-",
-            // encode to h264
-            "-c:v", "libx264",
-            &out_name
         ])
+        .args(soundtrack.map(|s| s.input_args(start, length)).unwrap_or_default())
+        .args(format.codec_args())
+        .arg(&out_name)
         .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
         .spawn()
         .context("Failed to spawn ffmpeg")?;
 
@@ -61,8 +246,35 @@ pub fn save_raw_frames_as_mp4(
         .context("Failed to get ffmpeg's stdin")?;
 
     let frames = frames.into_iter().flatten().collect::<Vec<u8>>();
-    stdin.write_all(&frames)?;
-    let _ = ffmpeg.wait_with_output()?;
+    let written = stdin.write_all(&frames);
+    let output = ffmpeg.wait_with_output()?;
+    // writing fails with a broken pipe if ffmpeg exited early, its error tells why
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to encode {out_name}: {err}",
+            err = String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    written.context("Failed to write the frames to ffmpeg")?;
 
     Ok(out_name)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_resampled() {
+        // a stall between the second and third frame, then faster than the rate
+        let times = [0.0, 0.1, 0.45, 0.5, 0.52, 0.55];
+        let frames = times
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (*t, vec![i as u8]))
+            .collect();
+
+        assert_eq!(resample_frames(frames, 10).concat(), [0, 1, 1, 1, 1, 3]);
+        assert!(resample_frames(Vec::new(), 10).is_empty());
+    }
+}
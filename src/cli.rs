@@ -0,0 +1,569 @@
+use crate::{
+    adapter::{parse_backends, AdapterOptions},
+    camera::Camera,
+    capture::{HdrFormat, Soundtrack, VideoFormat},
+    compare::CompareOptions,
+    export::ExportFormat,
+    ipc::Endpoint,
+    pp::ShaderFile,
+    target::TargetOptions,
+};
+use anyhow::Context;
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+use winit::dpi::PhysicalSize;
+
+pub const USAGE: &str = "\
+Usage: pussy [OPTIONS] <SHADERS>...
+       pussy render [OPTIONS] [RENDER OPTIONS] <SHADER>
+       pussy check [OPTIONS] [RENDER OPTIONS] <SHADER>
+       pussy compare [OPTIONS] [RENDER OPTIONS] [COMPARE OPTIONS] <SHADER>
+       pussy --bench <FRAMES> [OPTIONS] [RENDER OPTIONS] <SHADER>
+       pussy export [OPTIONS] [EXPORT OPTIONS] <SHADER>
+       pussy info [ADAPTER OPTIONS]
+
+Shaders are WGSL or SPIR-V (`*.spv`) files. The window can open several shaders or
+directories of them, PageDown/PageUp switch between them.
+With compare options the window and `render` show the shader left and the variant right
+of a split line (drag it with the mouse), V switches to their difference. `compare` prints
+how much they differ.
+
+Options:
+  --channel<N> <FILE>          Attach a video or audio file to the `Channel<N>` texture (N = 0..3)
+  --cube<N> <FILES>            Load an equirectangular image or 6 comma separated faces
+                               (+X,-X,+Y,-Y,+Z,-Z) into the `Cube<N>` texture (N = 0..1)
+  --volume<N> <PATH>           Load a directory of slices or a raw `*_<W>x<H>x<D>.raw` file
+                               into the `Volume<N>` texture (N = 0..1)
+  -D <NAME>[=<VALUE>]          Define a preprocessor name, as `#define NAME VALUE` at the top
+                               of the shader [default value: 1]
+  --defines <FILE>             Read defines from a file with a `<NAME>[=<VALUE>]` per line,
+                               `#` starts a comment (later defines win)
+  --fs-entry <NAME>            Fragment entry point to render, E cycles through all of them
+                               [default: fs_main, the first one for SPIR-V]
+  --vs-entry <NAME>            Vertex entry point [default: vs_main, the first one for SPIR-V,
+                               or a generated one if there is none]
+  --uniform <NAME>=<VALUES>    Declare `Uniforms.<NAME>`, a f32 or vec2..vec4 for 1 to 4 comma
+                               separated values (can be repeated)
+  --ipc <PATH|->               Accept JSON commands on a Unix socket at PATH, or on stdin
+                               with replies on stdout (window only)
+  --osc <[ADDRESS:]PORT>       Receive OSC messages over UDP, `/u/<NAME> <VALUES>` sets
+                               `Uniforms.<NAME>` (window only) [default address: 0.0.0.0]
+  --osc-map <FILE>             Map OSC addresses to uniforms and actions, with lines like
+                               `/1/fader1 uniform speed 0 4` or `/1/push1 next`
+  --mesh <FILE>                Draw an OBJ or glTF model instead of a fullscreen triangle
+  --camera <YAW,PITCH,DIST>    Initial orbit of the camera in degrees [default: 0,0,3]
+  --camera-target <X,Y,Z>      Point the camera orbits around [default: 0,0,0]
+  --fov <DEGREES>              Vertical field of view of the camera [default: 45]
+  --soundtrack <FILE>          Audio file muxed into saved videos
+  --soundtrack-offset <SECS>   Position in the soundtrack at `Time` = 0, can be negative [default: 0]
+  --video-format <mp4|webm>    Container of saved videos [default: mp4]
+  --target-format <FORMAT>     Format of the render target: rgba8, rgba16f or rgba32f [default: rgba8]
+  --tonemap <OP>               Tone mapping of the render target: none, reinhard or aces [default: none]
+  --exposure <STOPS>           Exposure applied before tone mapping [default: 0]
+  --output-space <SPACE>       How shader outputs are interpreted: linear (encoded to sRGB
+                               for display) or srgb (displayed as is) [default: linear]
+  --hdr-format <exr|hdr>       Format of screenshots of floating point targets [default: exr]
+  --bench <FRAMES>             Render frames headlessly and print their timings as JSON
+  -h, --help                   Print this message
+
+Adapter options:
+  --backend <BACKENDS>         Comma separated vulkan, gl, metal, dx12, dx11 or all
+                               [default: all, or the `WGPU_BACKEND` variable]
+  --adapter <INDEX|NAME>       Adapter by its index in `pussy info` or a part of its name
+  --power <low|high>           Preferred adapter when none is selected [default: low]
+  --software                   Only use a software adapter (llvmpipe, WARP, ...)
+  --features <FEATURES>        Comma separated optional features: timestamp-query,
+                               float32-filterable or push-constants, unsupported ones are skipped
+  --limits <LIMITS>            Device limits: default, downlevel or max (of the adapter) [default: default]
+
+Render options:
+  --start <SECS>               Time of the first frame [default: 0]
+  --duration <SECS>            Length of the rendered video [default: 10]
+  --fps <N>                    Frame rate of the rendered video [default: 30]
+  --size <WxH>                 Resolution of the rendered video [default: 1280x720]
+
+Compare options:
+  --compare <SHADER>           Compare with another shader
+  --compare-entry <NAME>       Compare with another fragment entry point
+  --compare-define <NAME>[=<VALUE>]
+                               Compare with an extra define (can be repeated)
+  --diff-scale <FACTOR>        Factor of the absolute difference in its view [default: 10]
+  --tolerance <VALUE>          Channel difference of pixels still counted as equal [default: 0]
+
+Export options:
+  --to <FORMATS>               Comma separated wgsl, glsl, spv, hlsl or msl [default: all of them]
+  --out-dir <DIR>              Directory the translated shaders are written into [default: .]";
+
+// only valid in headless modes
+const RENDER_OPTIONS: [&str; 4] = ["--start", "--duration", "--fps", "--size"];
+const EXPORT_OPTIONS: [&str; 2] = ["--to", "--out-dir"];
+
+pub enum Mode {
+    /// Interactive window with hot reloading.
+    View,
+    /// Headless rendering of a fixed time range into a video.
+    Render {
+        start: f32,
+        duration: f32,
+        fps: u32,
+        size: PhysicalSize<u32>,
+    },
+    /// Headless rendering of a time range, reporting pixels with invalid values.
+    Check {
+        start: f32,
+        duration: f32,
+        fps: u32,
+        size: PhysicalSize<u32>,
+    },
+    /// Headless rendering of a time range, reporting how much the compared variant differs.
+    Compare {
+        start: f32,
+        duration: f32,
+        fps: u32,
+        size: PhysicalSize<u32>,
+    },
+    /// Headless rendering of a number of frames, reporting how long they took.
+    Bench {
+        frames: u32,
+        start: f32,
+        fps: u32,
+        size: PhysicalSize<u32>,
+    },
+    /// Translates the shader with naga's backends.
+    Export {
+        formats: Vec<ExportFormat>,
+        out_dir: PathBuf,
+    },
+    /// Lists the available adapters.
+    Info,
+}
+
+pub struct Args {
+    pub mode: Mode,
+    /// Only the first one is rendered headlessly.
+    pub shaders: Vec<PathBuf>,
+    pub defines: Vec<(String, String)>,
+    pub fs_entry: Option<String>,
+    pub vs_entry: Option<String>,
+    pub uniforms: Vec<(String, Vec<f32>)>,
+    pub ipc: Option<Endpoint>,
+    pub osc: Option<SocketAddr>,
+    pub osc_map: Option<PathBuf>,
+    pub compare: CompareOptions,
+    pub channels: Vec<(usize, PathBuf)>,
+    pub cubes: Vec<(usize, String)>,
+    pub volumes: Vec<(usize, String)>,
+    pub mesh: Option<PathBuf>,
+    pub camera: Camera,
+    pub soundtrack: Option<Soundtrack>,
+    pub video_format: VideoFormat,
+    pub target: TargetOptions,
+    pub hdr_format: HdrFormat,
+    pub adapter: AdapterOptions,
+}
+
+impl Args {
+    pub fn parse() -> anyhow::Result<Self> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    fn parse_from(args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut args = args.peekable();
+        let command = args
+            .next_if(|a| ["render", "check", "compare", "export", "info"].contains(&a.as_str()));
+        let mut bench = None;
+        let mut render_options = Vec::new();
+        let mut export_options = Vec::new();
+        let mut shaders = Vec::new();
+        let mut defines = Vec::new();
+        let mut fs_entry = None;
+        let mut vs_entry = None;
+        let mut uniforms = Vec::<(String, Vec<f32>)>::new();
+        let mut ipc = None;
+        let mut osc = None;
+        let mut osc_map = None;
+        let mut compare = CompareOptions::default();
+        let mut channels = Vec::new();
+        let mut cubes = Vec::new();
+        let mut volumes = Vec::new();
+        let mut mesh = None;
+        let mut camera = Camera::default();
+        let mut start = 0.0;
+        let mut duration: f32 = 10.0;
+        let mut fps = 30;
+        let mut size = PhysicalSize::new(1280, 720);
+        let mut soundtrack = None;
+        let mut soundtrack_offset = 0.0;
+        let mut video_format = VideoFormat::default();
+        let mut target = TargetOptions::default();
+        let mut hdr_format = HdrFormat::default();
+        let mut adapter = AdapterOptions::default();
+        let mut formats = ExportFormat::ALL.to_vec();
+        let mut out_dir = PathBuf::from(".");
+
+        while let Some(arg) = args.next() {
+            if RENDER_OPTIONS.contains(&arg.as_str()) {
+                render_options.push(arg.clone());
+            }
+            if EXPORT_OPTIONS.contains(&arg.as_str()) {
+                export_options.push(arg.clone());
+            }
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                "--start" => start = parse(&mut args, &arg)?,
+                "--duration" => {
+                    duration = parse(&mut args, &arg)?;
+                    // NaN and negative durations would be cast to no frames
+                    if !(duration.is_finite() && duration > 0.0) {
+                        anyhow::bail!(
+                            "Invalid duration `{duration}` for `{arg}`, expected a positive number"
+                        );
+                    }
+                }
+                "--fps" => {
+                    fps = parse(&mut args, &arg)?;
+                    if fps == 0 {
+                        anyhow::bail!("Invalid frame rate `0` for `{arg}`, expected at least 1");
+                    }
+                }
+                "--size" => size = parse_size(&value(&mut args, &arg)?)?,
+                "--bench" => bench = Some(parse(&mut args, &arg)?),
+                "-D" => defines.push(parse_define(&value(&mut args, &arg)?)?),
+                _ if arg.starts_with("-D") => defines.push(parse_define(&arg[2..])?),
+                "--defines" => defines.extend(read_defines(Path::new(&value(&mut args, &arg)?))?),
+                "--fs-entry" => fs_entry = Some(value(&mut args, &arg)?),
+                "--vs-entry" => vs_entry = Some(value(&mut args, &arg)?),
+                "--uniform" => {
+                    let (name, value) = parse_uniform(&value(&mut args, &arg)?)?;
+                    if uniforms.iter().any(|(n, _)| *n == name) {
+                        anyhow::bail!("Uniform `{name}` is declared twice");
+                    }
+                    uniforms.push((name, value));
+                }
+                "--ipc" => ipc = Some(parse(&mut args, &arg)?),
+                "--osc" => osc = Some(parse_osc_address(&value(&mut args, &arg)?)?),
+                "--osc-map" => osc_map = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--compare" => compare.shader = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--compare-entry" => compare.fs_entry = Some(value(&mut args, &arg)?),
+                "--compare-define" => compare
+                    .defines
+                    .push(parse_define(&value(&mut args, &arg)?)?),
+                "--diff-scale" => compare.scale = parse(&mut args, &arg)?,
+                "--tolerance" => compare.tolerance = parse(&mut args, &arg)?,
+                "--mesh" => mesh = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--camera" => {
+                    let [yaw, pitch, distance] = parse_floats(&value(&mut args, &arg)?, &arg)?;
+                    // the view matrix is degenerate at the target itself
+                    if !(distance.is_finite() && distance > 0.0) {
+                        anyhow::bail!(
+                            "Invalid distance `{distance}` for `{arg}`, expected a positive number"
+                        );
+                    }
+                    camera.yaw = yaw;
+                    camera.pitch = pitch.clamp(-89.0, 89.0);
+                    camera.distance = distance;
+                }
+                "--camera-target" => {
+                    camera.target = parse_floats(&value(&mut args, &arg)?, &arg)?.into()
+                }
+                "--fov" => {
+                    let fov: f32 = parse(&mut args, &arg)?;
+                    // the projection is degenerate or flipped outside of this range
+                    if !(fov > 0.0 && fov < 180.0) {
+                        anyhow::bail!(
+                            "Invalid field of view `{fov}` for `{arg}`, expected degrees between 0 and 180"
+                        );
+                    }
+                    camera.fov = fov;
+                }
+                "--soundtrack" => soundtrack = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--soundtrack-offset" => soundtrack_offset = parse(&mut args, &arg)?,
+                "--video-format" => video_format = parse(&mut args, &arg)?,
+                "--target-format" => target.format = parse(&mut args, &arg)?,
+                "--tonemap" => target.tone_mapping = parse(&mut args, &arg)?,
+                "--exposure" => target.exposure = parse(&mut args, &arg)?,
+                "--output-space" => target.output_space = parse(&mut args, &arg)?,
+                "--hdr-format" => hdr_format = parse(&mut args, &arg)?,
+                "--backend" => adapter.backends = Some(parse_backends(&value(&mut args, &arg)?)?),
+                "--adapter" => adapter.adapter = Some(parse(&mut args, &arg)?),
+                "--power" => {
+                    adapter.power_preference = match value(&mut args, &arg)?.as_str() {
+                        "low" => wgpu::PowerPreference::LowPower,
+                        "high" => wgpu::PowerPreference::HighPerformance,
+                        v => anyhow::bail!("Invalid value `{v}` for `{arg}`, expected low or high"),
+                    }
+                }
+                "--software" => adapter.software = true,
+                "--features" => {
+                    adapter.features = value(&mut args, &arg)?
+                        .split(',')
+                        .map(|f| f.trim().parse())
+                        .collect::<anyhow::Result<_>>()?
+                }
+                "--limits" => adapter.limits = parse(&mut args, &arg)?,
+                "--to" => {
+                    formats = value(&mut args, &arg)?
+                        .split(',')
+                        .map(|f| f.trim().parse())
+                        .collect::<anyhow::Result<_>>()?
+                }
+                "--out-dir" => out_dir = PathBuf::from(value(&mut args, &arg)?),
+                _ if arg.starts_with("--channel") => {
+                    let idx = parse_index(&arg, "--channel", 4)?;
+                    channels.push((idx, PathBuf::from(value(&mut args, &arg)?)));
+                }
+                _ if arg.starts_with("--cube") => {
+                    cubes.push((parse_index(&arg, "--cube", 2)?, value(&mut args, &arg)?))
+                }
+                _ if arg.starts_with("--volume") => {
+                    volumes.push((parse_index(&arg, "--volume", 2)?, value(&mut args, &arg)?))
+                }
+                _ if arg.starts_with('-') => anyhow::bail!("Unknown option `{arg}`\n\n{USAGE}"),
+                _ => shaders.extend(expand_shaders(PathBuf::from(arg))?),
+            }
+        }
+
+        if let (Some(opt), false) = (export_options.first(), command.as_deref() == Some("export")) {
+            anyhow::bail!("Option `{opt}` is only valid for `export`\n\n{USAGE}");
+        }
+        let mode = match (command.as_deref(), bench) {
+            (Some("render"), _) => Mode::Render {
+                start,
+                duration,
+                fps,
+                size,
+            },
+            (Some("info"), _) => Mode::Info,
+            (Some("export"), _) if render_options.is_empty() => Mode::Export { formats, out_dir },
+            (Some("check"), _) => Mode::Check {
+                start,
+                duration,
+                fps,
+                size,
+            },
+            (Some("compare"), _) if compare.is_enabled() => Mode::Compare {
+                start,
+                duration,
+                fps,
+                size,
+            },
+            (Some("compare"), _) => anyhow::bail!(
+                "`compare` needs `--compare`, `--compare-entry` or `--compare-define`\n\n{USAGE}"
+            ),
+            (_, Some(frames)) => Mode::Bench {
+                frames,
+                start,
+                fps,
+                size,
+            },
+            _ => match render_options.first() {
+                Some(opt) => anyhow::bail!(
+                    "Option `{opt}` is only valid for `render`, `check` and `--bench`\n\n{USAGE}"
+                ),
+                None => Mode::View,
+            },
+        };
+
+        match (&mode, shaders.len()) {
+            // the only command without a shader
+            (Mode::Info, _) => {}
+            (_, 0) => anyhow::bail!("Shader path was not specifyed"),
+            (Mode::View, _) | (_, 1) => {}
+            _ => anyhow::bail!("Only one shader can be rendered headlessly"),
+        }
+        if !matches!(mode, Mode::View) {
+            if ipc.is_some() {
+                anyhow::bail!("Option `--ipc` is only valid for the window");
+            }
+            if osc.is_some() {
+                anyhow::bail!("Option `--osc` is only valid for the window");
+            }
+        }
+        if osc_map.is_some() && osc.is_none() {
+            anyhow::bail!("Option `--osc-map` requires `--osc`");
+        }
+
+        Ok(Self {
+            mode,
+            shaders,
+            defines,
+            fs_entry,
+            vs_entry,
+            uniforms,
+            ipc,
+            osc,
+            osc_map,
+            compare,
+            channels,
+            cubes,
+            volumes,
+            mesh,
+            camera,
+            soundtrack: soundtrack.map(|path| Soundtrack {
+                path,
+                offset: soundtrack_offset,
+            }),
+            video_format,
+            target,
+            hdr_format,
+            adapter,
+        })
+    }
+
+    /// The shader rendered headlessly, or the first one of the window.
+    pub fn shader(&self) -> &Path {
+        &self.shaders[0]
+    }
+
+    /// `path` preprocessed with the `-D` defines, using the selected entry points.
+    pub fn shader_file(&self, path: &Path) -> ShaderFile {
+        ShaderFile {
+            path: path.to_owned(),
+            defines: self.defines.clone(),
+            fs_entry: self.fs_entry.clone(),
+            vs_entry: self.vs_entry.clone(),
+            uniforms: self.uniforms.clone(),
+            source: None,
+        }
+    }
+
+    /// The variant `path` is compared with, if there is one.
+    /// Without `--compare` it's `path` itself, with other defines or another entry.
+    pub fn compare_file(&self, path: &Path) -> Option<ShaderFile> {
+        let compare = &self.compare;
+        if !compare.is_enabled() {
+            return None;
+        }
+        let mut file = self.shader_file(compare.shader.as_deref().unwrap_or(path));
+        // later defines win
+        file.defines.extend(compare.defines.iter().cloned());
+        if compare.fs_entry.is_some() {
+            file.fs_entry = compare.fs_entry.clone();
+        }
+        Some(file)
+    }
+}
+
+// `*.wgsl` and `*.spv` files of a directory in alphabetical order, or the path itself
+fn expand_shaders(path: PathBuf) -> anyhow::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path]);
+    }
+    let mut shaders = std::fs::read_dir(&path)
+        .with_context(|| format!("Failed to read {path:?}"))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|p| {
+            p.is_file()
+                && p.extension()
+                    .is_some_and(|ext| ext == "wgsl" || ext == "spv")
+        })
+        .collect::<Vec<_>>();
+    if shaders.is_empty() {
+        anyhow::bail!("{path:?} has no `*.wgsl` or `*.spv` files");
+    }
+    shaders.sort();
+
+    Ok(shaders)
+}
+
+// `NAME[=VALUE]`
+fn parse_define(v: &str) -> anyhow::Result<(String, String)> {
+    let (name, value) = v.split_once('=').unwrap_or((v, "1"));
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        anyhow::bail!("Invalid define `{v}`, expected <NAME>[=<VALUE>]");
+    }
+    Ok((name.to_owned(), value.to_owned()))
+}
+
+// `NAME[=VALUE]` lines, for presets like low/high quality that are kept in files
+fn read_defines(path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| {
+            parse_define(line).map_err(|e| anyhow::anyhow!("{}:{}: {e}", path.display(), i + 1))
+        })
+        .collect()
+}
+
+// `NAME=X[,Y[,Z[,W]]]`
+fn parse_uniform(v: &str) -> anyhow::Result<(String, Vec<f32>)> {
+    let uniform = v.split_once('=').and_then(|(name, value)| {
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        let value = value
+            .split(',')
+            .map(|f| f.trim().parse::<f32>().ok())
+            .collect::<Option<Vec<_>>>()
+            .filter(|v| v.len() <= 4)?;
+        valid.then(|| (name.to_owned(), value))
+    });
+    uniform.ok_or_else(|| {
+        anyhow::anyhow!("Invalid uniform `{v}`, expected <NAME>=<VALUES> with 1 to 4 numbers")
+    })
+}
+
+// `PORT` listens on all interfaces
+fn parse_osc_address(v: &str) -> anyhow::Result<SocketAddr> {
+    match v.parse::<u16>() {
+        Ok(port) => Ok(SocketAddr::from(([0, 0, 0, 0], port))),
+        Err(_) => v
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid OSC address `{v}`, expected [<ADDRESS>:]<PORT>")),
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, opt: &str) -> anyhow::Result<String> {
+    args.next()
+        .ok_or_else(|| anyhow::anyhow!("Option `{opt}` requires a value"))
+}
+
+fn parse<T: std::str::FromStr>(
+    args: &mut impl Iterator<Item = String>,
+    opt: &str,
+) -> anyhow::Result<T> {
+    let v = value(args, opt)?;
+    v.parse()
+        .map_err(|_| anyhow::anyhow!("Invalid value `{v}` for `{opt}`"))
+}
+
+fn parse_size(v: &str) -> anyhow::Result<PhysicalSize<u32>> {
+    v.split_once('x')
+        .and_then(|(w, h)| Some(PhysicalSize::new(w.parse().ok()?, h.parse().ok()?)))
+        .filter(|s| s.width > 0 && s.height > 0)
+        .ok_or_else(|| anyhow::anyhow!("Invalid size `{v}`, expected <WIDTH>x<HEIGHT>"))
+}
+
+// `N` comma separated numbers
+fn parse_floats<const N: usize>(v: &str, opt: &str) -> anyhow::Result<[f32; N]> {
+    let floats = v
+        .split(',')
+        .map(|f| f.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok()
+        .and_then(|f| f.try_into().ok());
+    floats.ok_or_else(|| anyhow::anyhow!("Invalid value `{v}` for `{opt}`, expected {N} numbers"))
+}
+
+// `<prefix><N>` with N < `count`
+fn parse_index(opt: &str, prefix: &str, count: usize) -> anyhow::Result<usize> {
+    opt[prefix.len()..]
+        .parse::<usize>()
+        .ok()
+        .filter(|i| *i < count)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Invalid option `{opt}`, expected {prefix}0..{prefix}{}",
+                count - 1
+            )
+        })
+}
@@ -0,0 +1,363 @@
+use crate::{
+    pp::{ShaderFile, ShaderSource},
+    target::{RenderTarget, TargetFormat},
+};
+use std::path::PathBuf;
+use winit::dpi::PhysicalSize;
+
+/// The variant the shader is compared with: another shader, another fragment entry
+/// and/or extra defines, any of them enables the comparison.
+#[derive(Clone)]
+pub struct CompareOptions {
+    pub shader: Option<PathBuf>,
+    pub fs_entry: Option<String>,
+    pub defines: Vec<(String, String)>,
+    /// Factor of the absolute difference in [`CompareView::Difference`].
+    pub scale: f32,
+    /// Largest channel difference of pixels that are still equal for `pussy compare`.
+    pub tolerance: f32,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            shader: None,
+            fs_entry: None,
+            defines: Vec::new(),
+            scale: 10.0,
+            tolerance: 0.0,
+        }
+    }
+}
+
+impl CompareOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.shader.is_some() || self.fs_entry.is_some() || !self.defines.is_empty()
+    }
+}
+
+/// How the two variants are shown.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum CompareView {
+    /// The shader left of the split line, the variant right of it.
+    #[default]
+    Split,
+    /// Absolute difference of the two, multiplied by the scale.
+    Difference,
+}
+
+/// Second variant of the shader, rendered with the same bindings into its own target.
+/// Both are composed into `composite`, which is presented instead of the shader's target.
+pub struct Comparison {
+    pub file: ShaderFile,
+    // last variant that passed validation
+    pub shader: ShaderSource,
+    pub pipeline: wgpu::RenderPipeline,
+    pub target: RenderTarget,
+    pub composite: RenderTarget,
+    composer: Composer,
+    pub view: CompareView,
+    /// Position of the split line, as a fraction of the width.
+    pub split: f32,
+    pub scale: f32,
+}
+
+impl Comparison {
+    pub fn new(
+        device: &wgpu::Device,
+        file: ShaderFile,
+        shader: ShaderSource,
+        pipeline: wgpu::RenderPipeline,
+        resolution: &PhysicalSize<u32>,
+        format: TargetFormat,
+        scale: f32,
+    ) -> Self {
+        Self {
+            target: RenderTarget::new(device, resolution, format, shader.outputs),
+            composite: RenderTarget::new(device, resolution, format, 1),
+            composer: Composer::new(device, format),
+            file,
+            shader,
+            pipeline,
+            view: CompareView::default(),
+            split: 0.5,
+            scale,
+        }
+    }
+
+    /// Recreates the targets for a new size or format, or number of outputs of the variant.
+    pub fn recreate_targets(
+        &mut self,
+        device: &wgpu::Device,
+        resolution: &PhysicalSize<u32>,
+        format: TargetFormat,
+    ) {
+        self.target = RenderTarget::new(device, resolution, format, self.shader.outputs);
+        self.composite = RenderTarget::new(device, resolution, format, 1);
+        if self.composer.format != format {
+            self.composer = Composer::new(device, format);
+        }
+    }
+
+    /// Attachment of the variant matching the shown one of the shader.
+    pub fn attachment(&self, shown: usize) -> &(wgpu::Texture, wgpu::TextureView) {
+        &self.target.attachments[shown.min(self.target.attachments.len() - 1)]
+    }
+
+    /// Composes `a` (from the shader) with the variant's attachment into `composite`.
+    pub fn compose(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        a: &wgpu::TextureView,
+        shown: usize,
+    ) {
+        let difference = if self.view == CompareView::Difference {
+            1.0
+        } else {
+            0.0
+        };
+        let width = self.composite.attachments[0].0.width() as f32;
+        let uniform = ComposeUniform {
+            params: [self.split * width, self.scale, difference, 0.0],
+        };
+        self.composer.draw(
+            device,
+            queue,
+            encoder,
+            [a, &self.attachment(shown).1],
+            &self.composite.attachments[0].1,
+            uniform,
+        );
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ComposeUniform {
+    // split line in pixels, scale of the difference, 1 for the difference view
+    params: [f32; 4],
+}
+
+/// Pass that composes the two variants, in the format of the targets.
+struct Composer {
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    buffer: wgpu::Buffer,
+    format: TargetFormat,
+}
+
+impl Composer {
+    fn new(device: &wgpu::Device, format: TargetFormat) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("compare"),
+            size: std::mem::size_of::<ComposeUniform>() as _,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                // loaded without a sampler, so 32 bit float targets are fine as well
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                texture(0),
+                texture(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("compare"),
+            source: wgpu::ShaderSource::Wgsl(compose_shader().into()),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("compare"),
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[&layout],
+                    push_constant_ranges: &[],
+                }),
+            ),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: crate::ctx::VS_ENTRY,
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: crate::ctx::FS_ENTRY,
+                targets: &[Some(format.texture_format().into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            layout,
+            pipeline,
+            buffer,
+            format,
+        }
+    }
+
+    fn draw(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        [a, b]: [&wgpu::TextureView; 2],
+        destination: &wgpu::TextureView,
+        uniform: ComposeUniform,
+    ) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(a),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(b),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("compare"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: destination,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// the split line is drawn in white, the difference keeps the values linear,
+// so exposure and tone mapping of the presentation apply to it as well
+fn compose_shader() -> String {
+    format!(
+        r#"
+@group(0) @binding(0) var A: texture_2d<f32>;
+@group(0) @binding(1) var B: texture_2d<f32>;
+@group(0) @binding(2) var<uniform> Params: vec4<f32>;
+
+{vertex_main}
+
+@fragment
+fn {fs_entry}(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {{
+    let a = textureLoad(A, vec2<i32>(frag_coord.xy), 0);
+    let b = textureLoad(B, vec2<i32>(frag_coord.xy), 0);
+    if Params.z > 0.5 {{
+        return vec4<f32>(abs(a.rgb - b.rgb) * Params.y, 1.0);
+    }}
+    if abs(frag_coord.x - Params.x) < 1.0 {{
+        return vec4<f32>(1.0);
+    }}
+    return select(a, b, frag_coord.x > Params.x);
+}}
+"#,
+        vertex_main = crate::pp::generate_vertex_main(),
+        fs_entry = crate::ctx::FS_ENTRY,
+    )
+}
+
+/// Differences between two frames decoded with [`TargetFormat::decode`].
+#[derive(Clone, Copy, Default)]
+pub struct DiffStats {
+    /// Largest difference of a color channel.
+    pub max: f32,
+    /// Pixels with a channel that differs by more than the tolerance, or is NaN in only one.
+    pub differing: usize,
+    sum_squared: f64,
+    channels: usize,
+}
+
+impl DiffStats {
+    pub fn count(a: &[f32], b: &[f32], tolerance: f32) -> Self {
+        let mut stats = Self::default();
+        for (a, b) in a.chunks_exact(4).zip(b.chunks_exact(4)) {
+            let mut differs = false;
+            for (a, b) in a[..3].iter().zip(&b[..3]) {
+                let d = (a - b).abs();
+                if d.is_nan() {
+                    differs |= a.is_nan() != b.is_nan();
+                    continue;
+                }
+                differs |= d > tolerance;
+                stats.max = stats.max.max(d);
+                stats.sum_squared += d as f64 * d as f64;
+                stats.channels += 1;
+            }
+            stats.differing += differs as usize;
+        }
+
+        stats
+    }
+
+    pub fn rmse(&self) -> f32 {
+        (self.sum_squared / self.channels.max(1) as f64).sqrt() as f32
+    }
+
+    /// Peak signal-to-noise ratio in dB for values in 0..1, infinite for equal frames.
+    pub fn psnr(&self) -> f32 {
+        20.0 * (1.0 / self.rmse()).log10()
+    }
+}
+
+impl std::ops::AddAssign for DiffStats {
+    fn add_assign(&mut self, other: Self) {
+        self.max = self.max.max(other.max);
+        self.differing += other.differing;
+        self.sum_squared += other.sum_squared;
+        self.channels += other.channels;
+    }
+}
+
+impl std::fmt::Display for DiffStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "max {:.6}, rmse {:.6}, psnr {:.2} dB, {} pixels differ",
+            self.max,
+            self.rmse(),
+            self.psnr(),
+            self.differing
+        )
+    }
+}
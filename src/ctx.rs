@@ -1,159 +1,803 @@
 use crate::{
+    adapter::AdapterOptions,
     bind::*,
-    pp::ShaderSource,
-    utils::{AllignedBufferSize, RawFrame},
+    compare::{CompareView, Comparison},
+    mesh::{Mesh, Vertex},
+    pp::{ShaderFile, ShaderSource},
+    target::{Presenter, RenderTarget, TargetFormat, TargetOptions, DEPTH_FORMAT},
+    timing::{FrameStats, GpuTimer, Hud, PASSES},
+    utils::{block_on, AllignedBufferSize, RawFrame},
+};
+use std::{
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
-use std::path::PathBuf;
 use winit::{dpi::PhysicalSize, window::Window};
 
 pub const VS_ENTRY: &str = "vs_main";
 pub const FS_ENTRY: &str = "fs_main";
+pub const CAPTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
 
 pub struct WgpuSetup {
+    pub instance: Arc<wgpu::Instance>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub adapter: wgpu::Adapter,
+    pub options: AdapterOptions,
 }
 
 impl WgpuSetup {
-    pub async fn new(wgpu_instance: &wgpu::Instance, surface: Option<&wgpu::Surface>) -> Self {
-        let adapter = wgpu_instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                force_fallback_adapter: false,
-                compatible_surface: surface,
-            })
-            .await
-            .expect("find an appropriate adapter");
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::default(),
-                    limits: wgpu::Limits::default(),
-                    label: None,
-                },
-                None,
-            )
-            .await
-            .expect("create device");
+    pub async fn new(
+        instance: Arc<wgpu::Instance>,
+        surface: Option<&wgpu::Surface>,
+        options: &AdapterOptions,
+    ) -> anyhow::Result<Self> {
+        let adapter = options.request_adapter(&instance, surface).await?;
+        let descriptor = options.device_descriptor(&adapter)?;
+        let (device, queue) = adapter.request_device(&descriptor, None).await?;
 
-        Self {
+        Ok(Self {
+            instance,
             device,
             queue,
             adapter,
-        }
+            options: options.clone(),
+        })
     }
 }
 
 pub struct WgpuContext {
-    pub window: Window,
+    pub window: Option<Window>,
     pub bindings: ShaderBindings,
     pub queue: wgpu::Queue,
-    pub output_buffer: Option<wgpu::Buffer>,
-    pub output_texture: Option<wgpu::Texture>,
     pub device: wgpu::Device,
     pub resolution: PhysicalSize<u32>,
+    pub mesh: Option<Mesh>,
     pipeline: wgpu::RenderPipeline,
-    config: wgpu::SurfaceConfiguration,
-    surface: wgpu::Surface,
-    shader_path: PathBuf,
+    // last shader that passed validation
+    shader: ShaderSource,
+    options: TargetOptions,
+    // the shader renders here, then it's presented into the surface or a capture texture
+    target: RenderTarget,
+    // attachment of the target that is presented
+    shown: usize,
+    // invalid values are highlighted instead of presenting the target
+    diagnostics: bool,
+    presenter: Presenter,
+    // `None` without timestamp queries
+    timer: Option<GpuTimer>,
+    // `None` when rendering headlessly
+    hud: Option<Hud>,
+    // `None` when rendering headlessly
+    surface: Option<(wgpu::Surface, wgpu::SurfaceConfiguration)>,
+    shader_file: ShaderFile,
+    shader_error: Option<String>,
+    // to recreate the context after the device was lost, the instance is kept
+    // since dropping it can invalidate the new one (GL shares the display)
+    instance: Arc<wgpu::Instance>,
+    adapter: AdapterOptions,
+    mesh_path: Option<PathBuf>,
+    // set by the error handler of the device
+    lost: Arc<AtomicBool>,
+    // fragment shaders can write storage buffers, which `debug_print` needs
+    writable_storage: bool,
+    float32_filterable: bool,
+    compare: Option<Comparison>,
 }
 
 impl WgpuContext {
-    pub async fn new(window: Window, shader_path: PathBuf) -> Self {
+    pub async fn new(
+        window: Window,
+        shader_file: ShaderFile,
+        options: TargetOptions,
+        mesh_path: Option<&Path>,
+        adapter: &AdapterOptions,
+    ) -> anyhow::Result<Self> {
         let resolution = window.inner_size();
-        let instance = wgpu::Instance::default();
-        let surface = unsafe { instance.create_surface(&window) }.expect("creating surface");
-        let init = WgpuSetup::new(&instance, Some(&surface)).await;
+        let instance = Arc::new(adapter.instance());
+        let surface = unsafe { instance.create_surface(&window) }?;
+        let init = WgpuSetup::new(instance, Some(&surface), adapter).await?;
 
         log::info!("Selected adapter: {:?}", init.adapter.get_info());
 
-        let surface_caps = surface.get_capabilities(&init.adapter);
-        let surface_format = surface_caps.formats[0];
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: resolution.width,
-            height: resolution.height,
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-        };
+        let config = configure_surface(&surface, &init, resolution);
+
+        Ok(Self::init(
+            init,
+            Some(window),
+            Some((surface, config)),
+            shader_file,
+            resolution,
+            options,
+            mesh_path,
+        ))
+    }
 
-        surface.configure(&init.device, &config);
+    /// Context without a window, frames can only be rendered into a [`FrameBuffer`].
+    pub async fn headless(
+        shader_file: ShaderFile,
+        resolution: PhysicalSize<u32>,
+        options: TargetOptions,
+        mesh_path: Option<&Path>,
+        adapter: &AdapterOptions,
+    ) -> anyhow::Result<Self> {
+        let init = WgpuSetup::new(Arc::new(adapter.instance()), None, adapter).await?;
+
+        log::info!("Selected adapter: {:?}", init.adapter.get_info());
+
+        Ok(Self::init(
+            init,
+            None,
+            None,
+            shader_file,
+            resolution,
+            options,
+            mesh_path,
+        ))
+    }
 
-        let bindings = ShaderBindings::new(&init.device);
-        let bind_group_layout = bindings.create_bind_group_layout(&init.device);
-        let shader_src = ShaderSource::validate(&shader_path, &bindings)
-            .map_err(|e| eprintln!("{e}"))
-            .unwrap_or_default();
+    fn init(
+        init: WgpuSetup,
+        window: Option<Window>,
+        surface: Option<(wgpu::Surface, wgpu::SurfaceConfiguration)>,
+        shader_file: ShaderFile,
+        resolution: PhysicalSize<u32>,
+        options: TargetOptions,
+        mesh_path: Option<&Path>,
+    ) -> Self {
+        // the shader is still shown without the mesh if it fails to load
+        let mesh = mesh_path.and_then(|path| {
+            Mesh::load(&init.device, path)
+                .map_err(|e| eprintln!("{e:?}"))
+                .ok()
+        });
+        let mut present_formats = vec![CAPTURE_FORMAT];
+        if let Some((_, ref config)) = surface {
+            present_formats.push(config.format);
+        }
+        // wgpu panics on uncaptured errors by default
+        let lost = Arc::new(AtomicBool::new(false));
+        let flag = lost.clone();
+        // a lost device can make submits and polls panic instead, see `probe_lost`
+        init.device.on_uncaptured_error(Box::new(move |e| {
+            if is_device_loss(&e) {
+                flag.store(true, Ordering::Relaxed);
+            } else {
+                log::error!("{e}");
+            }
+        }));
+        let presenter = Presenter::new(&init.device, &options, &present_formats);
+        let timer = GpuTimer::new(&init.device, &init.queue);
+        if timer.is_none() {
+            log::info!("Timestamp queries are not supported, GPU times are not available");
+        }
+        let hud = surface
+            .as_ref()
+            .map(|(_, config)| Hud::new(&init.device, config.format));
+        let mut bindings = ShaderBindings::new(&init.device);
+        bindings
+            .uniforms
+            .declare(&init.device, &init.queue, &shader_file.uniforms);
+        let writable_storage = init
+            .adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE)
+            && init.device.limits().max_storage_buffers_per_shader_stage > 0;
+        // the adapter's own format features only count with the feature from `--features`
+        let float32_filterable = init
+            .device
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+            && crate::adapter::filters_float32(&init.adapter);
+        let (shader_src, shader_error) = match validate_shader(
+            &shader_file,
+            &bindings.decls(),
+            mesh.is_some(),
+            options.format,
+            writable_storage,
+        ) {
+            Ok(ss) => (ss, None),
+            Err(e) => {
+                eprintln!("{e}");
+                (ShaderSource::default(), Some(e))
+            }
+        };
+        let target = RenderTarget::new(
+            &init.device,
+            &resolution,
+            options.format,
+            shader_src.outputs,
+        );
         let pipeline = create_render_pipeline(
             &init.device,
-            &bind_group_layout,
-            shader_src.as_str(),
-            config.format,
+            &bindings.create_bind_group_layout(&init.device, shader_src.debug),
+            &shader_src,
+            options.format.texture_format(),
+            mesh.is_some(),
         );
         Self {
             surface,
             device: init.device,
             queue: init.queue,
-            config,
+            options,
+            target,
+            shown: 0,
+            diagnostics: false,
+            presenter,
+            timer,
+            hud,
             window,
-            shader_path,
+            shader_file,
+            shader_error,
             pipeline,
+            shader: shader_src,
             bindings,
-            output_buffer: None,
-            output_texture: None,
             resolution,
+            mesh,
+            instance: init.instance,
+            adapter: init.options,
+            mesh_path: mesh_path.map(Path::to_path_buf),
+            lost,
+            writable_storage,
+            float32_filterable,
+            compare: None,
+        }
+    }
+
+    // against the current bindings, mesh, target format and device
+    fn validate(&self, file: &ShaderFile) -> Result<ShaderSource, String> {
+        validate_shader(
+            file,
+            &self.bindings.decls(),
+            self.mesh.is_some(),
+            self.options.format,
+            self.writable_storage,
+        )
+    }
+
+    /// Whether 32 bit float textures can be sampled, which needs `--features float32-filterable`.
+    pub fn float32_filterable(&self) -> bool {
+        self.float32_filterable
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+
+    /// Whether a panic of a call into wgpu was caused by losing the device,
+    /// which can't finish its work then either. Other panics are bugs.
+    pub fn probe_lost(&self) -> bool {
+        let lost = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            self.device.poll(wgpu::Maintain::Wait);
+        }))
+        .is_err();
+        if lost {
+            self.lost.store(true, Ordering::Relaxed);
+        }
+        lost
+    }
+
+    /// Recreates the device and everything made with it after the device was lost,
+    /// e.g. by a driver reset after a hang. The last valid shaders are rendered again,
+    /// not the files as they are now.
+    pub fn recover(&mut self) -> anyhow::Result<()> {
+        log::error!(
+            "Device lost while running {:?}, recreating it",
+            self.shader_file.path
+        );
+        // a window can only have one surface
+        self.surface = None;
+        let surface = match self.window {
+            Some(ref window) => Some(unsafe { self.instance.create_surface(window) }?),
+            None => None,
+        };
+        let init = block_on(WgpuSetup::new(
+            self.instance.clone(),
+            surface.as_ref(),
+            &self.adapter,
+        ))?;
+        let surface = surface.map(|surface| {
+            let config = configure_surface(&surface, &init, self.resolution);
+            (surface, config)
+        });
+        let mesh_path = self.mesh_path.take();
+        let compare = self.compare.take();
+        let shader = std::mem::take(&mut self.shader);
+        let shader_error = self.shader_error.take();
+        // values set while running outlive the device
+        let uniforms = self
+            .bindings
+            .uniforms
+            .values()
+            .map(|(name, value)| (name.to_owned(), value.to_vec()))
+            .collect::<Vec<_>>();
+        *self = Self::init(
+            init,
+            self.window.take(),
+            surface,
+            self.shader_file.clone(),
+            self.resolution,
+            self.options,
+            mesh_path.as_deref(),
+        );
+        self.shader = shader;
+        self.shader_error = shader_error;
+        for (name, value) in uniforms {
+            let _ = self.bindings.uniforms.set(&self.queue, &name, &value);
+        }
+        if let Some(mut compare) = compare {
+            let file = compare.file.clone();
+            let scale = compare.scale;
+            let shader = std::mem::take(&mut compare.shader);
+            // the old one only passes on the view
+            self.compare = Some(compare);
+            self.set_compare(file, shader, scale);
+        }
+        self.rebuild_pipeline();
+
+        Ok(())
+    }
+
+    pub fn window(&self) -> &Window {
+        self.window.as_ref().expect("context has a window")
+    }
+
+    /// 8 bit targets are replaced with a floating point one while diagnostics are on,
+    /// since they can't hold invalid values.
+    pub fn target_format(&self) -> TargetFormat {
+        match self.options.format {
+            TargetFormat::Rgba8 if self.diagnostics => TargetFormat::Rgba32Float,
+            format => format,
+        }
+    }
+
+    pub fn toggle_diagnostics(&mut self) {
+        self.diagnostics = !self.diagnostics;
+        log::info!(
+            "Diagnostics {}",
+            if self.diagnostics {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        self.rebuild_pipeline();
+    }
+
+    /// Switches to another shader file, the default shader is shown while it's invalid.
+    pub fn load_shader(&mut self, file: ShaderFile) {
+        self.bindings
+            .uniforms
+            .declare(&self.device, &self.queue, &file.uniforms);
+        self.shader_file = file;
+        self.shader = match self.validate(&self.shader_file) {
+            Ok(ss) => {
+                self.shader_error = None;
+                ss
+            }
+            Err(err) => {
+                crate::utils::print(&err);
+                self.shader_error = Some(err);
+                ShaderSource::default()
+            }
+        };
+        self.rebuild_pipeline();
+    }
+
+    /// Why the shader file failed to validate the last time, the previous shader is still shown.
+    pub fn shader_error(&self) -> Option<&str> {
+        self.shader_error.as_deref()
+    }
+
+    /// Fragment entry that is rendered.
+    pub fn fs_entry(&self) -> &str {
+        &self.shader.fs_entry
+    }
+
+    /// Renders `file` next to the shader with the same bindings, see [`Comparison`].
+    /// The view and split line of a previous comparison are kept.
+    /// If `file` is invalid the default shader is compared and the error is returned.
+    pub fn compare_with(&mut self, file: ShaderFile, scale: f32) -> Result<(), String> {
+        let (shader, result) = match self.validate(&file) {
+            Ok(ss) => (ss, Ok(())),
+            Err(err) => (ShaderSource::default(), Err(err)),
+        };
+        self.set_compare(file, shader, scale);
+        result
+    }
+
+    fn set_compare(&mut self, file: ShaderFile, shader: ShaderSource, scale: f32) {
+        let pipeline = create_render_pipeline(
+            &self.device,
+            &self
+                .bindings
+                .create_bind_group_layout(&self.device, shader.debug),
+            &shader,
+            self.target_format().texture_format(),
+            self.mesh.is_some(),
+        );
+        let mut compare = Comparison::new(
+            &self.device,
+            file,
+            shader,
+            pipeline,
+            &self.resolution,
+            self.target_format(),
+            scale,
+        );
+        if let Some(previous) = self.compare.take() {
+            compare.view = previous.view;
+            compare.split = previous.split;
+        }
+        self.compare = Some(compare);
+    }
+
+    /// Switches between the split and the difference view of the comparison.
+    pub fn toggle_compare_view(&mut self) {
+        if let Some(ref mut compare) = self.compare {
+            compare.view = match compare.view {
+                CompareView::Split => CompareView::Difference,
+                CompareView::Difference => CompareView::Split,
+            };
+        }
+    }
+
+    /// Position of the split line in pixels, while it's shown.
+    pub fn split_line(&self) -> Option<f32> {
+        self.compare
+            .as_ref()
+            .filter(|c| c.view == CompareView::Split)
+            .map(|c| c.split * self.resolution.width as f32)
+    }
+
+    pub fn move_split_line(&mut self, x: f32) {
+        if let Some(ref mut compare) = self.compare {
+            compare.split = (x / self.resolution.width as f32).clamp(0.0, 1.0);
         }
     }
 
+    /// Renders the next fragment entry of the shader, it stays selected across reloads.
+    pub fn cycle_fs_entry(&mut self) {
+        let entries = &self.shader.fs_entries;
+        let Some(i) = entries.iter().position(|e| *e == self.shader.fs_entry) else {
+            return;
+        };
+        if entries.len() < 2 {
+            return;
+        }
+        let next = entries[(i + 1) % entries.len()].clone();
+        log::info!("Rendering `{next}`");
+        self.shader_file.fs_entry = Some(next);
+        self.rebuild_shader();
+    }
+
     pub fn rebuild_shader(&mut self) {
         // crate::utils::clear_screen();
-        match ShaderSource::validate(&self.shader_path, &self.bindings) {
+        let mut rebuild = false;
+        match self.validate(&self.shader_file) {
             Ok(ss) => {
-                let bgl = self.bindings.create_bind_group_layout(&self.device);
-                self.pipeline =
-                    create_render_pipeline(&self.device, &bgl, ss.as_str(), self.config.format)
+                self.shader = ss;
+                self.shader_error = None;
+                rebuild = true;
+            }
+            Err(err) => {
+                crate::utils::print(&err);
+                self.shader_error = Some(err);
+            }
+        }
+        if let Some(ref compare) = self.compare {
+            match self.validate(&compare.file) {
+                Ok(ss) => {
+                    if let Some(ref mut compare) = self.compare {
+                        compare.shader = ss;
+                    }
+                    rebuild = true;
+                }
+                Err(err) => crate::utils::print(&err),
+            }
+        }
+        if rebuild {
+            self.rebuild_pipeline();
+        }
+    }
+
+    // from the last valid shader, the target is recreated if it doesn't match anymore
+    fn rebuild_pipeline(&mut self) {
+        let outputs = self.shader.outputs;
+        self.pipeline = create_render_pipeline(
+            &self.device,
+            &self
+                .bindings
+                .create_bind_group_layout(&self.device, self.shader.debug),
+            &self.shader,
+            self.target_format().texture_format(),
+            self.mesh.is_some(),
+        );
+        if outputs as usize != self.target.attachments.len()
+            || self.target_format() != self.target.format
+        {
+            self.target = RenderTarget::new(
+                &self.device,
+                &self.resolution,
+                self.target_format(),
+                outputs,
+            );
+            self.shown = self.shown.min(outputs as usize - 1);
+        }
+
+        let format = self.target_format();
+        if let Some(ref mut compare) = self.compare {
+            compare.pipeline = create_render_pipeline(
+                &self.device,
+                &self
+                    .bindings
+                    .create_bind_group_layout(&self.device, compare.shader.debug),
+                &compare.shader,
+                format.texture_format(),
+                self.mesh.is_some(),
+            );
+            if compare.shader.outputs as usize != compare.target.attachments.len()
+                || format != compare.target.format
+            {
+                compare.recreate_targets(&self.device, &self.resolution, format);
             }
-            Err(err) => println!("{err}"),
         }
     }
 
     pub fn resize(&mut self, new_resolution: &PhysicalSize<u32>) {
         if new_resolution.width > 0 && new_resolution.height > 0 {
-            self.config.width = new_resolution.width;
-            self.config.height = new_resolution.height;
-            self.surface.configure(&self.device, &self.config);
+            if let Some((ref surface, ref mut config)) = self.surface {
+                config.width = new_resolution.width;
+                config.height = new_resolution.height;
+                surface.configure(&self.device, config);
+            }
+            self.target = RenderTarget::new(
+                &self.device,
+                new_resolution,
+                self.target_format(),
+                self.target.attachments.len() as _,
+            );
+            if let Some(ref mut compare) = self.compare {
+                compare.recreate_targets(&self.device, new_resolution, self.target.format);
+            }
             self.resolution = *new_resolution;
         }
     }
 
-    pub fn render_frame(&self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+    /// Presents the next `@location(n)` output of the fragment shader.
+    pub fn cycle_attachment(&mut self) {
+        self.shown = (self.shown + 1) % self.target.attachments.len();
+        log::info!(
+            "Showing attachment {shown} of {count}",
+            shown = self.shown,
+            count = self.target.attachments.len()
+        );
+    }
+
+    /// With `stats` the frame time graph is drawn over the frame.
+    pub fn render_frame(&mut self, stats: Option<&FrameStats>) -> Result<(), wgpu::SurfaceError> {
+        let Some((ref surface, ref config)) = self.surface else {
+            return Ok(());
+        };
+        let output = surface.get_current_texture()?;
         let texture_view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        let bg = self.bindings.create_bind_group(&self.device);
-
-        render_frame(&mut encoder, &self.pipeline, &bg, &texture_view);
+        let (mut encoder, timed) = self.render_and_present(&texture_view, config.format);
+        if let (Some(hud), Some(stats)) = (&self.hud, stats) {
+            hud.draw(
+                &self.queue,
+                &mut encoder,
+                &texture_view,
+                config.height,
+                stats,
+            );
+        }
 
         self.queue.submit(Some(encoder.finish()));
         // without this surface will not be updated
         output.present();
+        if timed {
+            self.timer.as_mut().map(GpuTimer::map);
+        }
 
         Ok(())
     }
 
+    /// Frame as it is shown in the window, always in the `CAPTURE_FORMAT`.
     pub fn render_into_frame_buffer(&mut self) -> FrameBuffer {
-        let bg = self.bindings.create_bind_group(&self.device);
         let texture = create_texture(&self.device, &self.resolution);
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let (encoder, timed) = self.render_and_present(&texture_view, CAPTURE_FORMAT);
+        let frame = FrameBuffer::new(&self.device, &self.queue, encoder, &texture);
+        if timed {
+            self.timer.as_mut().map(GpuTimer::map);
+        }
+
+        frame
+    }
+
+    /// Like [`Self::render_into_frame_buffer`], with the CPU time of each of `timing::PASSES`.
+    /// Every pass is submitted on its own and waited for, so it's a fallback for devices
+    /// without timestamp queries that is only used by `--bench`.
+    pub fn render_into_frame_buffer_timed(&mut self) -> (FrameBuffer, [f32; PASSES.len()]) {
+        let texture = create_texture(&self.device, &self.resolution);
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let time = |encode: &dyn Fn(&mut wgpu::CommandEncoder)| {
+            let start = std::time::Instant::now();
+            let mut encoder = create_encoder(&self.device);
+            encode(&mut encoder);
+            let idx = self.queue.submit(Some(encoder.finish()));
+            self.device
+                .poll(wgpu::Maintain::WaitForSubmissionIndex(idx));
+            start.elapsed().as_secs_f32() * 1000.0
+        };
+        let times = [
+            time(&|encoder| self.encode_target(encoder)),
+            time(&|encoder| {
+                self.presenter.draw(
+                    &self.device,
+                    encoder,
+                    &self.presented().1,
+                    &texture_view,
+                    CAPTURE_FORMAT,
+                    self.diagnostics,
+                )
+            }),
+        ];
+        let frame = FrameBuffer::new(
+            &self.device,
+            &self.queue,
+            create_encoder(&self.device),
+            &texture,
+        );
+
+        (frame, times)
+    }
+
+    /// Milliseconds spent on the GPU in each of `timing::PASSES` of some recent frame,
+    /// `None` without timestamp queries or if there are no new results yet.
+    pub fn gpu_times(&mut self, wait: bool) -> Option<[f32; PASSES.len()]> {
+        self.timer.as_mut()?.poll(&self.device, wait)
+    }
+
+    pub fn has_gpu_timer(&self) -> bool {
+        self.timer.is_some()
+    }
+
+    // the returned flag tells if timestamps were written and have to be mapped after submitting
+    fn render_and_present(
+        &self,
+        view: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    ) -> (wgpu::CommandEncoder, bool) {
+        let timer = self.timer.as_ref().filter(|t| t.is_idle());
+        let mut encoder = create_encoder(&self.device);
+        if let Some(timer) = timer {
+            timer.write(&mut encoder, 0);
+        }
+        self.encode_target(&mut encoder);
+        if let Some(timer) = timer {
+            timer.write(&mut encoder, 1);
+        }
+        self.presenter.draw(
+            &self.device,
+            &mut encoder,
+            &self.presented().1,
+            view,
+            format,
+            self.diagnostics,
+        );
+        if let Some(timer) = timer {
+            timer.write(&mut encoder, 2);
+            timer.resolve(&mut encoder);
+        }
+
+        (encoder, timer.is_some())
+    }
+
+    /// Untouched content of the shown attachment (or its composition with the compared
+    /// variant), in the format of `target_format`.
+    pub fn render_into_target_buffer(&mut self) -> FrameBuffer {
+        let mut encoder = create_encoder(&self.device);
+        self.encode_target(&mut encoder);
+        let texture = &self.presented().0;
+
+        FrameBuffer::new(&self.device, &self.queue, encoder, texture)
+    }
+
+    /// Untouched shown attachments of the shader and of the compared variant.
+    pub fn render_into_compare_buffers(&mut self) -> Option<(FrameBuffer, FrameBuffer)> {
+        let compare = self.compare.as_ref()?;
+        let mut encoder = create_encoder(&self.device);
+        self.encode_target(&mut encoder);
+        let a = FrameBuffer::new(
+            &self.device,
+            &self.queue,
+            encoder,
+            &self.target.attachments[self.shown].0,
+        );
+        let b = FrameBuffer::new(
+            &self.device,
+            &self.queue,
+            create_encoder(&self.device),
+            &compare.attachment(self.shown).0,
+        );
 
-        FrameBuffer::new(&self.device, &self.queue, &texture, &self.pipeline, &bg)
+        Some((a, b))
+    }
+
+    // the shown attachment, or its composition with the compared variant
+    fn presented(&self) -> &(wgpu::Texture, wgpu::TextureView) {
+        match self.compare {
+            Some(ref compare) => &compare.composite.attachments[0],
+            None => &self.target.attachments[self.shown],
+        }
+    }
+
+    /// Value written by the shader at `x`, `y` into the shown attachment during the last frame,
+    /// right of the split line the one written by the compared variant.
+    pub fn read_pixel(&self, x: u32, y: u32) -> Option<[f32; 4]> {
+        if x >= self.resolution.width || y >= self.resolution.height {
+            return None;
+        }
+        let texture = match self.compare {
+            Some(ref compare) if self.split_line().is_some_and(|split| x as f32 > split) => {
+                &compare.attachment(self.shown).0
+            }
+            _ => &self.target.attachments[self.shown].0,
+        };
+        let frame = FrameBuffer::region(
+            &self.device,
+            &self.queue,
+            create_encoder(&self.device),
+            texture,
+            wgpu::Origin3d { x, y, z: 0 },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        )
+        .read(&self.device);
+
+        self.target_format().decode(&frame).try_into().ok()
+    }
+
+    fn encode_target(&self, encoder: &mut wgpu::CommandEncoder) {
+        let bg = self
+            .bindings
+            .create_bind_group(&self.device, self.shader.debug);
+        render_frame(
+            encoder,
+            &self.pipeline,
+            &bg,
+            &self.target,
+            self.mesh.as_ref(),
+        );
+        if let Some(ref compare) = self.compare {
+            render_frame(
+                encoder,
+                &compare.pipeline,
+                &self
+                    .bindings
+                    .create_bind_group(&self.device, compare.shader.debug),
+                &compare.target,
+                self.mesh.as_ref(),
+            );
+            compare.compose(
+                &self.device,
+                &self.queue,
+                encoder,
+                &self.target.attachments[self.shown].1,
+                self.shown,
+            );
+        }
     }
 }
 
@@ -161,29 +805,47 @@ pub struct FrameBuffer {
     pub buffer: wgpu::Buffer,
     pub buffer_size: AllignedBufferSize,
     pub submission_idx: wgpu::SubmissionIndex,
+    pub format: wgpu::TextureFormat,
 }
 
 impl FrameBuffer {
+    /// Submits `encoder` with an extra command that copies `texture` into the buffer.
     pub fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        encoder: wgpu::CommandEncoder,
         texture: &wgpu::Texture,
-        pipeline: &wgpu::RenderPipeline,
-        bind_group: &wgpu::BindGroup,
     ) -> Self {
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let texture_size = texture.size();
-        let mut encoder = create_encoder(device);
-        let buffer_size = AllignedBufferSize::new(texture_size.width, texture_size.height);
+        Self::region(
+            device,
+            queue,
+            encoder,
+            texture,
+            wgpu::Origin3d::ZERO,
+            texture.size(),
+        )
+    }
+
+    /// Same as [`FrameBuffer::new`], but only `size` texels from `origin` are copied.
+    pub fn region(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mut encoder: wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        origin: wgpu::Origin3d,
+        size: wgpu::Extent3d,
+    ) -> Self {
+        let bytes_per_pixel = texture.format().block_size(None).unwrap_or(4);
+        let buffer_size = AllignedBufferSize::new(size.width, size.height, bytes_per_pixel);
         let buffer = create_buffer(device, buffer_size.buffer_size as _);
-        render_frame(&mut encoder, pipeline, bind_group, &texture_view);
-        copy_texture_to_buffer(&mut encoder, texture, &buffer, &buffer_size);
+        copy_texture_to_buffer(&mut encoder, texture, origin, size, &buffer, &buffer_size);
         let submission_idx = queue.submit(Some(encoder.finish()));
 
         Self {
             buffer,
             buffer_size,
             submission_idx,
+            format: texture.format(),
         }
     }
 
@@ -196,10 +858,25 @@ impl FrameBuffer {
         receiver.await.unwrap().unwrap();
     }
 
+    /// Blocks until the frame is rendered, for use outside of the event loop.
+    pub fn read(self, device: &wgpu::Device) -> RawFrame {
+        let buffer_slice = self.buffer.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |result| {
+            if let Err(e) = result {
+                log::error!("Failed to map frame buffer: {e}");
+            }
+        });
+        device.poll(wgpu::Maintain::WaitForSubmissionIndex(
+            self.submission_idx.clone(),
+        ));
+
+        self.extract_data()
+    }
+
     pub fn extract_data(self) -> RawFrame {
         let buffer_slice = self.buffer.slice(..);
         let padded_data = buffer_slice.get_mapped_range();
-        let frame = padded_data
+        let mut frame = padded_data
             .chunks(self.buffer_size.padded_bytes_per_row as _)
             .flat_map(|ch| &ch[..self.buffer_size.unpadded_bytes_per_row as _])
             .copied()
@@ -207,47 +884,78 @@ impl FrameBuffer {
         drop(padded_data);
         self.buffer.unmap();
 
+        // frames are always handed out as rgba
+        crate::target::swizzle_to_rgba(self.format, &mut frame);
+
         frame
     }
 }
 
+/// Draws `mesh` if there is one or a single triangle that covers the whole target.
 pub fn render_frame(
     encoder: &mut wgpu::CommandEncoder,
     pipeline: &wgpu::RenderPipeline,
     bind_group: &wgpu::BindGroup,
-    texture_view: &wgpu::TextureView,
+    target: &RenderTarget,
+    mesh: Option<&Mesh>,
 ) {
+    let color_attachments = target
+        .attachments
+        .iter()
+        .map(|(_, view)| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.1,
+                        g: 0.2,
+                        b: 0.3,
+                        a: 1.0,
+                    }),
+                    store: true,
+                },
+            })
+        })
+        .collect::<Vec<_>>();
     let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
         label: None,
-        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-            view: texture_view,
-            resolve_target: None,
-            ops: wgpu::Operations {
-                load: wgpu::LoadOp::Clear(wgpu::Color {
-                    r: 0.1,
-                    g: 0.2,
-                    b: 0.3,
-                    a: 1.0,
-                }),
-                store: true,
-            },
-        })],
-        depth_stencil_attachment: None,
+        color_attachments: &color_attachments,
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: &target.depth,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: false,
+            }),
+            stencil_ops: None,
+        }),
     });
 
     render_pass.set_bind_group(0, bind_group, &[]);
     render_pass.set_pipeline(pipeline);
-    render_pass.draw(0..3, 0..1);
+    match mesh {
+        Some(mesh) => {
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..mesh.index_count, 0, 0..1);
+        }
+        None => render_pass.draw(0..3, 0..1),
+    }
 }
 
 pub fn copy_texture_to_buffer(
     encoder: &mut wgpu::CommandEncoder,
     texture: &wgpu::Texture,
+    origin: wgpu::Origin3d,
+    size: wgpu::Extent3d,
     buffer: &wgpu::Buffer,
     buf_size: &AllignedBufferSize,
 ) {
     encoder.copy_texture_to_buffer(
-        texture.as_image_copy(),
+        wgpu::ImageCopyTexture {
+            origin,
+            ..texture.as_image_copy()
+        },
         wgpu::ImageCopyBuffer {
             buffer,
             layout: wgpu::ImageDataLayout {
@@ -256,7 +964,7 @@ pub fn copy_texture_to_buffer(
                 rows_per_image: None,
             },
         },
-        texture.size(),
+        size,
     );
 }
 
@@ -271,9 +979,9 @@ pub fn create_texture(device: &wgpu::Device, size: &PhysicalSize<u32>) -> wgpu::
         mip_level_count: 1,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        format: CAPTURE_FORMAT,
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
-        view_formats: &[wgpu::TextureFormat::Rgba8UnormSrgb],
+        view_formats: &[CAPTURE_FORMAT],
     })
 }
 
@@ -286,39 +994,96 @@ pub fn create_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
     })
 }
 
+// wgpu 0.17 has no callback for a lost device, calls into it fail with wgpu-core's
+// `DeviceError::Lost` somewhere in the chain of causes then
+fn is_device_loss(e: &wgpu::Error) -> bool {
+    if let wgpu::Error::OutOfMemory { .. } = e {
+        return true;
+    }
+    std::iter::successors(Some(e as &dyn std::error::Error), |e| e.source())
+        .any(|e| e.to_string() == "Parent device is lost")
+}
+
+// the shader also has to fit the device, `debug_print` writes a storage buffer
+// in the fragment stage, which downlevel devices can't
+fn validate_shader(
+    file: &ShaderFile,
+    bindings: &BindingDecls,
+    mesh: bool,
+    format: TargetFormat,
+    writable_storage: bool,
+) -> Result<ShaderSource, String> {
+    let shader = ShaderSource::validate(file, bindings, mesh, format)?;
+    if shader.debug && !writable_storage {
+        return Err(format!(
+            "{:?}: `debug_print` needs storage buffers that fragment shaders can write, \
+             which the adapter doesn't support",
+            file.path
+        ));
+    }
+    Ok(shader)
+}
+
 pub fn create_render_pipeline(
     device: &wgpu::Device,
     bind_group_layout: &wgpu::BindGroupLayout,
-    shader_src: &str,
+    shader: &ShaderSource,
     texture_format: wgpu::TextureFormat,
+    mesh: bool,
 ) -> wgpu::RenderPipeline {
-    let module = &device.create_shader_module(wgpu::ShaderModuleDescriptor {
+    let wgsl = device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: None,
-        source: wgpu::ShaderSource::Wgsl(shader_src.into()),
+        source: wgpu::ShaderSource::Wgsl(shader.as_str().into()),
     });
+    let spirv = shader.spirv().map(|module| {
+        let vertex = module
+            .entry_points
+            .iter()
+            .any(|ep| ep.stage == naga::ShaderStage::Vertex);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Naga(std::borrow::Cow::Owned(module.clone())),
+        });
+        (module, vertex)
+    });
+    // SPIR-V shaders without a vertex entry use the generated one
+    let (vertex_module, fragment_module) = match spirv {
+        Some((ref module, true)) => (module, module),
+        Some((ref module, false)) => (&wgsl, module),
+        None => (&wgsl, &wgsl),
+    };
 
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
         bind_group_layouts: &[bind_group_layout],
         push_constant_ranges: &[],
     });
+    // every `@location(n)` output gets its own attachment of the same format
+    let targets = (0..shader.outputs)
+        .map(|_| {
+            Some(wgpu::ColorTargetState {
+                format: texture_format,
+                // 32 bit float targets are not blendable
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })
+        })
+        .collect::<Vec<_>>();
+    // the fullscreen vertex stage has no vertex buffers
+    let buffers = if mesh { vec![Vertex::layout()] } else { vec![] };
 
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: None,
         layout: Some(&pipeline_layout),
         vertex: wgpu::VertexState {
-            module,
-            entry_point: VS_ENTRY,
-            buffers: &[],
+            module: vertex_module,
+            entry_point: &shader.vs_entry,
+            buffers: &buffers,
         },
         fragment: Some(wgpu::FragmentState {
-            module,
-            entry_point: FS_ENTRY,
-            targets: &[Some(wgpu::ColorTargetState {
-                format: texture_format,
-                blend: Some(wgpu::BlendState::REPLACE),
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
+            module: fragment_module,
+            entry_point: &shader.fs_entry,
+            targets: &targets,
         }),
         primitive: wgpu::PrimitiveState {
             // every three vertices will corespond to one triangle
@@ -330,7 +1095,13 @@ pub fn create_render_pipeline(
             cull_mode: Some(wgpu::Face::Back),
             ..Default::default()
         },
-        depth_stencil: None,
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
         multisample: wgpu::MultisampleState {
             count: 1,
             mask: !0,
@@ -345,3 +1116,134 @@ pub fn create_render_pipeline(
 pub fn create_encoder(device: &wgpu::Device) -> wgpu::CommandEncoder {
     device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None })
 }
+
+fn configure_surface(
+    surface: &wgpu::Surface,
+    init: &WgpuSetup,
+    resolution: PhysicalSize<u32>,
+) -> wgpu::SurfaceConfiguration {
+    let surface_caps = surface.get_capabilities(&init.adapter);
+    // the presenter encodes colors by itself for non-sRGB formats,
+    // but the hardware conversion is preferred
+    let surface_format = surface_caps
+        .formats
+        .iter()
+        .copied()
+        .find(|f| f.is_srgb())
+        .unwrap_or(surface_caps.formats[0]);
+    log::info!("Surface format: {surface_format:?}");
+    let config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format: surface_format,
+        width: resolution.width,
+        height: resolution.height,
+        present_mode: surface_caps.present_modes[0],
+        alpha_mode: surface_caps.alpha_modes[0],
+        view_formats: vec![],
+    };
+
+    surface.configure(&init.device, &config);
+
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::target::ColorSpace;
+
+    const GRADIENT: &str = "
+@fragment
+fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+    let c = frag_coord.x / Resolution.x;
+    return vec4<f32>(c, c, c, 1.0);
+}
+";
+
+    // renders an 8x1 gradient, or `None` without an adapter to render it
+    fn gradient_context(output_space: ColorSpace) -> Option<WgpuContext> {
+        let size = PhysicalSize::new(8, 1);
+        let shader_file = ShaderFile {
+            path: "gradient.wgsl".into(),
+            defines: vec![],
+            fs_entry: None,
+            vs_entry: None,
+            uniforms: vec![],
+            source: Some(GRADIENT.to_owned()),
+        };
+        let options = TargetOptions {
+            output_space,
+            ..Default::default()
+        };
+        let adapter = AdapterOptions::default();
+        let ctx = WgpuContext::headless(shader_file, size, options, None, &adapter);
+        let mut ctx = match block_on(ctx) {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                eprintln!("Skipping the gradient test: {e}");
+                return None;
+            }
+        };
+        assert!(ctx.shader_error().is_none());
+        ctx.bindings.resolution.update(&ctx.queue, [8.0, 1.0]);
+        Some(ctx)
+    }
+
+    fn capture_gradient(output_space: ColorSpace) -> Option<RawFrame> {
+        let mut ctx = gradient_context(output_space)?;
+        Some(ctx.render_into_frame_buffer().read(&ctx.device))
+    }
+
+    #[test]
+    fn captures_linear_gradient_as_srgb() {
+        let Some(frame) = capture_gradient(ColorSpace::Linear) else {
+            return;
+        };
+        for (i, px) in frame.chunks_exact(4).enumerate() {
+            let c = (i as f32 + 0.5) / 8.0;
+            let srgb = if c <= 0.0031308 {
+                c * 12.92
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            };
+            let expected = (srgb * 255.0).round();
+            // the target is 8 bit as well, so values can be off by one step
+            assert!(
+                px[..3].iter().all(|&v| (v as f32 - expected).abs() <= 1.0),
+                "{i}: {px:?} != {expected}"
+            );
+            assert_eq!(px[3], 255);
+        }
+    }
+
+    #[test]
+    fn captures_srgb_gradient_as_is() {
+        let Some(frame) = capture_gradient(ColorSpace::Srgb) else {
+            return;
+        };
+        for (i, px) in frame.chunks_exact(4).enumerate() {
+            let expected = (i as f32 + 0.5) / 8.0 * 255.0;
+            assert!(
+                px[..3].iter().all(|&v| (v as f32 - expected).abs() <= 1.0),
+                "{i}: {px:?} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn recovering_keeps_the_shader() {
+        let Some(mut ctx) = gradient_context(ColorSpace::Srgb) else {
+            return;
+        };
+        let before = ctx.render_into_frame_buffer().read(&ctx.device);
+        let source = ctx.shader.as_str().to_owned();
+        // the file is broken by now, the shader that was valid is rendered again
+        ctx.shader_file.source = Some("fn broken(".to_owned());
+        ctx.recover().unwrap();
+        ctx.bindings.resolution.update(&ctx.queue, [8.0, 1.0]);
+
+        assert_eq!(ctx.shader.as_str(), source);
+        assert!(ctx.shader_error().is_none());
+        assert_eq!(ctx.render_into_frame_buffer().read(&ctx.device), before);
+    }
+}
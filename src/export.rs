@@ -0,0 +1,155 @@
+use crate::{
+    bind::BindingDecls,
+    pp::{ShaderFile, ShaderSource},
+    target::TargetFormat,
+};
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+
+/// Languages `pussy export` translates shaders into.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Wgsl,
+    Glsl,
+    Spirv,
+    Hlsl,
+    Msl,
+}
+
+impl ExportFormat {
+    pub const ALL: [Self; 5] = [Self::Wgsl, Self::Glsl, Self::Spirv, Self::Hlsl, Self::Msl];
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wgsl" => Ok(Self::Wgsl),
+            "glsl" => Ok(Self::Glsl),
+            "spv" => Ok(Self::Spirv),
+            "hlsl" => Ok(Self::Hlsl),
+            "msl" => Ok(Self::Msl),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported export format `{s}`, expected wgsl, glsl, spv, hlsl or msl"
+            )),
+        }
+    }
+}
+
+/// Writes the shader as the context would render it (preprocessed, with the bindings and
+/// the generated vertex stage) into `out_dir`, returns the written files.
+/// GLSL has a file per entry point, the other formats one for the whole module.
+/// SPIR-V shaders are translated without the generated vertex stage.
+/// With `mesh` set the generated vertex stage is the one for meshes, no GPU is needed.
+/// `target` limits the number of outputs, like it does when rendering.
+pub fn export(
+    file: &ShaderFile,
+    mesh: bool,
+    target: TargetFormat,
+    formats: &[ExportFormat],
+    out_dir: &Path,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let bindings = BindingDecls::new(
+        file.uniforms
+            .iter()
+            .map(|(name, v)| (name.as_str(), v.len())),
+    );
+    let shader =
+        ShaderSource::validate(file, &bindings, mesh, target).map_err(anyhow::Error::msg)?;
+    let (module, info) = shader.module();
+    let stem = file
+        .path
+        .file_stem()
+        .with_context(|| format!("{:?} is not a file", file.path))?
+        .to_string_lossy();
+    std::fs::create_dir_all(out_dir).with_context(|| format!("Failed to create {out_dir:?}"))?;
+
+    let mut outputs = Vec::<(PathBuf, Vec<u8>)>::new();
+    for format in formats {
+        match format {
+            // the source itself keeps comments and formatting
+            ExportFormat::Wgsl if shader.spirv().is_none() => {
+                outputs.push((format!("{stem}.wgsl").into(), shader.as_str().into()))
+            }
+            ExportFormat::Wgsl => {
+                let wgsl = naga::back::wgsl::write_string(
+                    module,
+                    info,
+                    naga::back::wgsl::WriterFlags::empty(),
+                )
+                .context("Failed to translate to WGSL")?;
+                outputs.push((format!("{stem}.wgsl").into(), wgsl.into()));
+            }
+            ExportFormat::Glsl => {
+                let options = naga::back::glsl::Options {
+                    version: naga::back::glsl::Version::Desktop(450),
+                    ..Default::default()
+                };
+                for ep in &module.entry_points {
+                    let pipeline_options = naga::back::glsl::PipelineOptions {
+                        shader_stage: ep.stage,
+                        entry_point: ep.name.clone(),
+                        multiview: None,
+                    };
+                    let mut glsl = String::new();
+                    naga::back::glsl::Writer::new(
+                        &mut glsl,
+                        module,
+                        info,
+                        &options,
+                        &pipeline_options,
+                        Default::default(),
+                    )
+                    .and_then(|mut writer| writer.write())
+                    .with_context(|| format!("Failed to translate `{}` to GLSL", ep.name))?;
+                    outputs.push((format!("{stem}.{}.glsl", ep.name).into(), glsl.into()));
+                }
+            }
+            ExportFormat::Spirv => {
+                let words = naga::back::spv::write_vec(module, info, &Default::default(), None)
+                    .context("Failed to translate to SPIR-V")?;
+                outputs.push((
+                    format!("{stem}.spv").into(),
+                    bytemuck::cast_slice(&words).to_vec(),
+                ));
+            }
+            ExportFormat::Hlsl => {
+                let mut hlsl = String::new();
+                naga::back::hlsl::Writer::new(&mut hlsl, &Default::default())
+                    .write(module, info)
+                    .context("Failed to translate to HLSL")?;
+                outputs.push((format!("{stem}.hlsl").into(), hlsl.into()));
+            }
+            ExportFormat::Msl => {
+                let (msl, _) = naga::back::msl::write_string(
+                    module,
+                    info,
+                    &Default::default(),
+                    &Default::default(),
+                )
+                .context("Failed to translate to MSL")?;
+                outputs.push((format!("{stem}.metal").into(), msl.into()));
+            }
+        }
+    }
+
+    // nothing is written if any of the translations failed or would overwrite the shader,
+    // like `foo.spv` exported to SPIR-V next to itself
+    let outputs = outputs
+        .into_iter()
+        .map(|(name, data)| (out_dir.join(name), data))
+        .collect::<Vec<_>>();
+    let shader = file.path.canonicalize().ok();
+    if let Some((path, _)) = outputs
+        .iter()
+        .find(|(path, _)| shader.is_some() && path.canonicalize().ok() == shader)
+    {
+        anyhow::bail!("Exporting would overwrite the shader {path:?}, use --out-dir");
+    }
+    for (path, data) in &outputs {
+        std::fs::write(path, data).with_context(|| format!("Failed to write {path:?}"))?;
+    }
+
+    Ok(outputs.into_iter().map(|(path, _)| path).collect())
+}
@@ -0,0 +1,69 @@
+use crate::{audio::Audio, bind::TextureBinding, video::Video};
+use std::path::Path;
+
+const AUDIO_EXTENSIONS: &[&str] = &["wav", "ogg", "mp3", "flac"];
+
+/// A file attached to one of the `Channel<N>` textures.
+pub enum Input {
+    Video(Video),
+    Audio(Audio),
+}
+
+impl Input {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let is_audio = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+
+        if is_audio {
+            Ok(Self::Audio(Audio::open(path)?))
+        } else {
+            Ok(Self::Video(Video::open(path)?))
+        }
+    }
+
+    /// Uploads the state of the input at `time` into `binding`.
+    /// With `realtime` unset the video decoding is waited for and audio is not played,
+    /// which is what offline rendering needs.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        q: &wgpu::Queue,
+        binding: &mut TextureBinding,
+        time: f32,
+        realtime: bool,
+        paused: bool,
+    ) {
+        match self {
+            Self::Video(video) => {
+                if let Some(frame) = video.frame_at(time, !realtime) {
+                    let size = wgpu::Extent3d {
+                        width: video.width,
+                        height: video.height,
+                        depth_or_array_layers: 1,
+                    };
+                    binding.update(device, q, size, wgpu::TextureFormat::Rgba8UnormSrgb, &frame);
+                }
+            }
+            Self::Audio(audio) => {
+                if realtime {
+                    audio.sync_playback(time, paused);
+                }
+                let size = wgpu::Extent3d {
+                    width: crate::audio::TEXTURE_WIDTH,
+                    height: crate::audio::TEXTURE_HEIGHT,
+                    depth_or_array_layers: 1,
+                };
+                binding.update(
+                    device,
+                    q,
+                    size,
+                    crate::audio::TEXTURE_FORMAT,
+                    &audio.texture_at(time),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+/// Where commands are read from, see [`Server`].
+pub enum Endpoint {
+    /// JSON lines on stdin, the replies are printed to stdout.
+    Stdin,
+    /// Unix socket, any number of clients can be connected.
+    Socket(PathBuf),
+}
+
+impl std::str::FromStr for Endpoint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err(anyhow::anyhow!("Expected a socket path or `-` for stdin")),
+            "-" => Ok(Self::Stdin),
+            path => Ok(Self::Socket(PathBuf::from(path))),
+        }
+    }
+}
+
+/// A JSON line like `{"cmd": "set", "name": "speed", "value": 0.5}`.
+#[derive(Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Command {
+    /// Shows a shader file, it's opened next to the others if it isn't yet.
+    Load {
+        path: PathBuf,
+    },
+    /// Renders WGSL code in place of the shown file until a shader is loaded or switched to.
+    Source {
+        code: String,
+    },
+    /// Sets a uniform declared with `--uniform`.
+    Set {
+        name: String,
+        value: Value,
+    },
+    /// Sets `Time` in seconds.
+    Time {
+        value: f32,
+    },
+    Pause,
+    Resume,
+    /// Saves the presented frame as a PNG file.
+    Screenshot {
+        path: PathBuf,
+    },
+    StartRecording,
+    /// Saves the recorded frames as a video, named after the current time without a path.
+    StopRecording {
+        path: Option<PathBuf>,
+    },
+    /// Replies with the shown shader, its error, the time, the uniforms, ...
+    State,
+}
+
+/// A number or up to 4 of them.
+#[derive(Deserialize)]
+#[serde(try_from = "Components")]
+pub struct Value(Vec<f32>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Components {
+    Scalar(f32),
+    Vector(Vec<f32>),
+}
+
+impl TryFrom<Components> for Value {
+    type Error = String;
+
+    fn try_from(components: Components) -> Result<Self, Self::Error> {
+        match components {
+            Components::Scalar(v) => Ok(Self(vec![v])),
+            Components::Vector(v) if (1..=4).contains(&v.len()) => Ok(Self(v)),
+            Components::Vector(v) => Err(format!("expected 1 to 4 numbers, got {}", v.len())),
+        }
+    }
+}
+
+impl Value {
+    pub fn to_vec(&self) -> Vec<f32> {
+        self.0.clone()
+    }
+}
+
+/// A command and the client waiting for its reply.
+pub struct Request {
+    pub command: Command,
+    reply: mpsc::Sender<String>,
+}
+
+impl Request {
+    /// Replies with `{"ok": true}` and the fields of an object,
+    /// or with `{"ok": false, "error": "..."}`.
+    pub fn reply(self, result: anyhow::Result<serde_json::Value>) {
+        let mut reply = serde_json::Map::new();
+        reply.insert("ok".into(), result.is_ok().into());
+        match result {
+            Ok(serde_json::Value::Object(fields)) => reply.extend(fields),
+            Ok(_) => {}
+            Err(e) => {
+                reply.insert("error".into(), format!("{e:#}").into());
+            }
+        }
+        // the client is gone
+        let _ = self
+            .reply
+            .send(serde_json::Value::Object(reply).to_string());
+    }
+}
+
+/// Reads commands in background threads, they are handled by the event loop.
+pub struct Server {
+    receiver: mpsc::Receiver<Request>,
+    // removed when the server is dropped
+    socket: Option<PathBuf>,
+}
+
+impl Server {
+    pub fn new(endpoint: &Endpoint) -> anyhow::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let socket = match endpoint {
+            Endpoint::Stdin => {
+                crate::utils::reserve_stdout();
+                std::thread::spawn(move || {
+                    serve(std::io::stdin().lock(), std::io::stdout(), &sender)
+                });
+                None
+            }
+            Endpoint::Socket(path) => {
+                listen(path, sender)?;
+                Some(path.clone())
+            }
+        };
+
+        Ok(Self { receiver, socket })
+    }
+
+    /// Commands received since the last call, each has to be replied to.
+    pub fn requests(&self) -> Vec<Request> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        if let Some(ref path) = self.socket {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// a line is replied to before the next one is read, so replies are in order
+fn serve(reader: impl BufRead, mut writer: impl Write, sender: &mpsc::Sender<Request>) {
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Command>(&line) {
+            Ok(command) => {
+                let (reply, receiver) = mpsc::channel();
+                if sender.send(Request { command, reply }).is_err() {
+                    break;
+                }
+                match receiver.recv() {
+                    Ok(reply) => reply,
+                    Err(_) => break,
+                }
+            }
+            Err(e) => json!({ "ok": false, "error": format!("Invalid command: {e}") }).to_string(),
+        };
+        if writeln!(writer, "{reply}")
+            .and_then(|_| writer.flush())
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn listen(path: &Path, sender: mpsc::Sender<Request>) -> anyhow::Result<()> {
+    use std::os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    };
+
+    // left behind by an instance that didn't exit cleanly
+    if std::fs::metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        if UnixStream::connect(path).is_ok() {
+            anyhow::bail!("{path:?} is used by another instance");
+        }
+        std::fs::remove_file(path).with_context(|| format!("Failed to remove {path:?}"))?;
+    }
+    let listener =
+        UnixListener::bind(path).with_context(|| format!("Failed to listen on {path:?}"))?;
+    log::info!("Listening for commands on {path:?}");
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let sender = sender.clone();
+            let reader = stream.and_then(|s| Ok((s.try_clone()?, s)));
+            match reader {
+                Ok((reader, writer)) => {
+                    std::thread::spawn(move || {
+                        serve(std::io::BufReader::new(reader), writer, &sender)
+                    });
+                }
+                Err(e) => log::error!("Failed to accept a client: {e}"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn listen(path: &Path, _: mpsc::Sender<Request>) -> anyhow::Result<()> {
+    anyhow::bail!("Can't listen on {path:?}, Unix sockets aren't supported here, use `--ipc -`")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // replies of `serve` to `input`, commands are answered like the event loop would
+    fn replies(input: &str) -> Vec<serde_json::Value> {
+        let (sender, receiver) = mpsc::channel::<Request>();
+        let handler = std::thread::spawn(move || {
+            for request in receiver {
+                let reply = match request.command {
+                    Command::Set {
+                        ref name,
+                        ref value,
+                    } => Ok(json!({ "name": name, "value": value.to_vec() })),
+                    Command::Pause => Ok(json!({})),
+                    _ => Err(anyhow::anyhow!("Unexpected command")),
+                };
+                request.reply(reply);
+            }
+        });
+        let mut output = Vec::new();
+        serve(input.as_bytes(), &mut output, &sender);
+        drop(sender);
+        handler.join().unwrap();
+
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    fn error(reply: &serde_json::Value) -> &str {
+        assert_eq!(reply["ok"], false, "{reply}");
+        reply["error"].as_str().unwrap()
+    }
+
+    #[test]
+    fn replies_to_commands_in_order() {
+        let replies = replies(
+            r#"{"cmd": "set", "name": "speed", "value": 0.5}
+
+{"cmd": "pause"}
+{"cmd": "set", "name": "color", "value": [1, 0.5, 0]}
+"#,
+        );
+
+        assert_eq!(
+            replies,
+            [
+                json!({ "ok": true, "name": "speed", "value": [0.5] }),
+                json!({ "ok": true }),
+                json!({ "ok": true, "name": "color", "value": [1.0, 0.5, 0.0] }),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        let replies = replies("{\"cmd\": \"pause\"\nnot json\n{\"cmd\": \"pause\"}\n");
+
+        assert_eq!(replies.len(), 3);
+        assert!(error(&replies[0]).starts_with("Invalid command: "));
+        assert!(error(&replies[1]).starts_with("Invalid command: "));
+        // the connection is still usable
+        assert_eq!(replies[2], json!({ "ok": true }));
+    }
+
+    #[test]
+    fn rejects_unknown_commands_and_fields() {
+        let replies = replies(
+            r#"{"cmd": "time", "value": 1.0, "speed": 2.0}
+{"cmd": "jump"}
+"#,
+        );
+
+        assert!(error(&replies[0]).contains("unknown field `speed`"));
+        assert!(error(&replies[1]).contains("unknown variant `jump`"));
+    }
+
+    #[test]
+    fn rejects_set_with_the_wrong_arity() {
+        let replies = replies(
+            r#"{"cmd": "set", "name": "speed"}
+{"cmd": "set", "name": "speed", "value": []}
+{"cmd": "set", "name": "speed", "value": [1, 2, 3, 4, 5]}
+{"cmd": "set", "name": "speed", "value": "fast"}
+"#,
+        );
+
+        assert_eq!(replies.len(), 4);
+        assert!(error(&replies[0]).contains("missing field `value`"));
+        assert!(error(&replies[1]).contains("expected 1 to 4 numbers, got 0"));
+        assert!(error(&replies[2]).contains("expected 1 to 4 numbers, got 5"));
+        assert!(error(&replies[3]).starts_with("Invalid command: "));
+    }
+}
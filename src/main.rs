@@ -1,50 +1,694 @@
+mod adapter;
+mod audio;
 mod bind;
+mod camera;
 mod capture;
+mod cli;
+mod compare;
 mod ctx;
+mod export;
+mod input;
+mod ipc;
+mod mesh;
+mod osc;
 mod pp;
+mod target;
+mod texture;
+mod timing;
 mod utils;
+mod video;
 
-use crate::{ctx::WgpuContext, utils::Msg};
+use crate::{
+    bind::{DebugOutput, DEBUG_ENTRIES},
+    camera::{Camera, CameraController},
+    capture::{HdrFormat, Soundtrack},
+    cli::{Args, Mode},
+    compare::DiffStats,
+    ctx::WgpuContext,
+    export::ExportFormat,
+    input::Input,
+    ipc::{Command, Server},
+    osc::{Action, Listener, Target},
+    pp::ShaderFile,
+    target::{PixelStats, TargetFormat, TargetOptions},
+    timing::{FrameStats, Summary, PASSES},
+    utils::{Channel, Msg},
+};
+use glam::Mat4;
+use serde_json::json;
+use std::{
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+};
 use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
     event::*,
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
 
-async fn draw(shader_path: &str) {
-    let shader_path = std::path::PathBuf::from(shader_path);
+// seconds to jump forward/backward with the arrow keys
+const SEEK_STEP: f32 = 1.0;
+// pixels from the split line of a comparison where dragging moves it
+const SPLIT_GRAB: f32 = 6.0;
+const TITLE: &str = "puss";
+// frame rate of recordings, captured frames are repeated or dropped to match it
+const RECORDING_FPS: u32 = 30;
+
+fn open_inputs(channels: &[(usize, PathBuf)]) -> anyhow::Result<Vec<(usize, Input)>> {
+    channels
+        .iter()
+        .map(|(idx, path)| Ok((*idx, Input::open(path)?)))
+        .collect()
+}
+
+fn update_inputs(
+    ctx: &mut WgpuContext,
+    inputs: &mut [(usize, Input)],
+    time: f32,
+    realtime: bool,
+    paused: bool,
+) {
+    for (idx, input) in inputs.iter_mut() {
+        if let Some(binding) = ctx.bindings.channel_mut(*idx) {
+            input.update(&ctx.device, &ctx.queue, binding, time, realtime, paused);
+        }
+    }
+}
+
+// the mesh is fitted into the -1..1 cube, which the default camera looks at
+fn update_transforms(ctx: &mut WgpuContext, camera: &Camera) {
+    let q = &ctx.queue;
+    let b = &mut ctx.bindings;
+    let aspect = ctx.resolution.width as f32 / ctx.resolution.height as f32;
+    let model = ctx
+        .mesh
+        .as_ref()
+        .map(|m| m.model_matrix)
+        .unwrap_or(Mat4::IDENTITY);
+    let view = camera.view();
+    let projection = camera.projection(aspect);
+    b.model_matrix.update(q, model.to_cols_array_2d());
+    b.camera_position.update(q, camera.position().to_array());
+    b.camera_view.update(q, view.to_cols_array_2d());
+    b.camera_projection.update(q, projection.to_cols_array_2d());
+    b.camera_inverse_view
+        .update(q, view.inverse().to_cols_array_2d());
+    b.camera_inverse_projection
+        .update(q, projection.inverse().to_cols_array_2d());
+}
+
+// value under the cursor, in full precision for floating point targets
+fn inspect_pixel(ctx: &WgpuContext, cursor: PhysicalPosition<f32>) -> Option<String> {
+    if cursor.x < 0.0 || cursor.y < 0.0 {
+        return None;
+    }
+    let (x, y) = (cursor.x as u32, cursor.y as u32);
+    let [r, g, b, a] = ctx.read_pixel(x, y)?;
+    let res = ctx.resolution.cast::<f32>();
+    let (u, v) = ((x as f32 + 0.5) / res.width, (y as f32 + 0.5) / res.height);
+
+    Some(if ctx.target_format().is_hdr() {
+        format!("({x}, {y}) uv ({u:.4}, {v:.4}) rgba({r:?}, {g:?}, {b:?}, {a:?})")
+    } else {
+        format!("({x}, {y}) uv ({u:.4}, {v:.4}) rgba({r:.4}, {g:.4}, {b:.4}, {a:.4})")
+    })
+}
+
+// frame has to be rendered with the pixel selected by `DebugOutput::select`
+fn print_debug_output(ctx: &WgpuContext) {
+    let output = ctx.bindings.debug.read(&ctx.device, &ctx.queue);
+    let [x, y] = output.pixel;
+    crate::utils::print(format_args!("debug_print at ({x}, {y}):"));
+    for entry in output.entries() {
+        crate::utils::print(format_args!(
+            "  {id}: {value:?}",
+            id = entry.id,
+            value = &entry.value[..entry.len.min(4) as usize]
+        ));
+    }
+    if output.count as usize > DEBUG_ENTRIES {
+        crate::utils::print(format_args!(
+            "  {} more values dropped",
+            output.count as usize - DEBUG_ENTRIES
+        ));
+    }
+}
+
+fn load_textures(ctx: &mut WgpuContext, args: &Args) -> anyhow::Result<()> {
+    for (idx, spec) in args.cubes.iter() {
+        let max_dimension = ctx.device.limits().max_texture_dimension_2d;
+        let tex = crate::texture::load_cubemap(spec, max_dimension, ctx.float32_filterable())?;
+        if let Some(binding) = ctx.bindings.cube_mut(*idx) {
+            binding.update(&ctx.device, &ctx.queue, tex.size, tex.format, &tex.data);
+        }
+    }
+    for (idx, spec) in args.volumes.iter() {
+        let max_dimension = ctx.device.limits().max_texture_dimension_3d;
+        let tex = crate::texture::load_volume(spec, max_dimension, ctx.float32_filterable())?;
+        if let Some(binding) = ctx.bindings.volume_mut(*idx) {
+            binding.update(&ctx.device, &ctx.queue, tex.size, tex.format, &tex.data);
+        }
+    }
+
+    Ok(())
+}
+
+/// Context of the headless modes with the inputs of `args`, rendering frames at `fps` from `start`.
+struct Headless {
+    ctx: WgpuContext,
+    inputs: Vec<(usize, Input)>,
+    camera: Camera,
+    start: f32,
+    fps: u32,
+}
+
+impl Headless {
+    /// Fails if the shader, or the variant it's compared with, doesn't validate.
+    async fn new(
+        args: &Args,
+        target: TargetOptions,
+        size: PhysicalSize<u32>,
+        start: f32,
+        fps: u32,
+    ) -> anyhow::Result<Self> {
+        let inputs = open_inputs(&args.channels)?;
+        let mut ctx = WgpuContext::headless(
+            args.shader_file(args.shader()),
+            size,
+            target,
+            args.mesh.as_deref(),
+            &args.adapter,
+        )
+        .await?;
+        // the error itself is printed while creating the context
+        if ctx.shader_error().is_some() {
+            anyhow::bail!("{:?} failed to validate", args.shader());
+        }
+        load_textures(&mut ctx, args)?;
+        if let Some(file) = args.compare_file(args.shader()) {
+            let path = file.path.clone();
+            if let Err(e) = ctx.compare_with(file, args.compare.scale) {
+                eprintln!("{e}");
+                anyhow::bail!("Compared shader {path:?} failed to validate");
+            }
+        }
+
+        Ok(Self {
+            ctx,
+            inputs,
+            camera: args.camera,
+            start,
+            fps,
+        })
+    }
+
+    /// Updates the bindings for each of the `frames` before `render` is called with its time,
+    /// fails if the device is lost.
+    fn run(
+        &mut self,
+        frames: impl Iterator<Item = u32>,
+        mut render: impl FnMut(&mut WgpuContext, u32, f32) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        for i in frames {
+            let time = self.start + i as f32 / self.fps as f32;
+            let ctx = &mut self.ctx;
+            let size = ctx.resolution;
+            ctx.bindings.time.update(&ctx.queue, time);
+            ctx.bindings
+                .resolution
+                .update(&ctx.queue, [size.width as f32, size.height as f32]);
+            update_inputs(ctx, &mut self.inputs, time, false, false);
+            update_transforms(ctx, &self.camera);
+            // wgpu panics when the device is lost during a submit or a poll
+            match std::panic::catch_unwind(AssertUnwindSafe(|| render(ctx, i, time))) {
+                Ok(result) => result?,
+                Err(_) if ctx.probe_lost() => {}
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+            if ctx.is_lost() {
+                anyhow::bail!("The device was lost while rendering the frame at {time:.3}s");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn render(
+    args: Args,
+    start: f32,
+    duration: f32,
+    fps: u32,
+    size: PhysicalSize<u32>,
+) -> anyhow::Result<()> {
+    let mut headless = Headless::new(&args, args.target, size, start, fps).await?;
+    let n_frames = (duration * fps as f32).ceil() as u32;
+    let mut frames = Vec::with_capacity(n_frames as _);
+
+    log::info!("Rendering {n_frames} frames");
+    headless.run(0..n_frames, |ctx, _, _| {
+        frames.push(ctx.render_into_frame_buffer().read(&ctx.device));
+        Ok(())
+    })?;
+
+    // without an explicit soundtrack the first audio input is used
+    let soundtrack = args.soundtrack.clone().or_else(|| {
+        headless.inputs.iter().find_map(|(_, input)| match input {
+            Input::Audio(audio) => Some(Soundtrack {
+                path: audio.path.clone(),
+                offset: 0.0,
+            }),
+            _ => None,
+        })
+    });
+    let file = crate::capture::save_raw_frames_as_video(
+        frames,
+        &size,
+        fps,
+        args.video_format,
+        start,
+        soundtrack.as_ref(),
+        None,
+    )?;
+    println!("{file} saved!");
+
+    Ok(())
+}
+
+async fn check(
+    args: Args,
+    start: f32,
+    duration: f32,
+    fps: u32,
+    size: PhysicalSize<u32>,
+) -> anyhow::Result<()> {
+    // values are checked before any conversion
+    let target = TargetOptions {
+        format: TargetFormat::Rgba32Float,
+        ..args.target
+    };
+    let mut headless = Headless::new(&args, target, size, start, fps).await?;
+    let n_frames = (duration * fps as f32).ceil() as u32;
+    let mut total = PixelStats::default();
+
+    log::info!("Checking {n_frames} frames");
+    headless.run(0..n_frames, |ctx, _, time| {
+        let frame = ctx.render_into_target_buffer().read(&ctx.device);
+        let stats = PixelStats::count(&ctx.target_format().decode(&frame));
+        if !stats.is_empty() {
+            println!("{time:.3}s: {stats}");
+        }
+        total += stats;
+        Ok(())
+    })?;
+
+    println!("Total over {n_frames} frames: {total}");
+    if total.nan + total.infinite > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+async fn compare(
+    args: Args,
+    start: f32,
+    duration: f32,
+    fps: u32,
+    size: PhysicalSize<u32>,
+) -> anyhow::Result<()> {
+    // like `check`, values are compared before any conversion
+    let target = TargetOptions {
+        format: TargetFormat::Rgba32Float,
+        ..args.target
+    };
+    let mut headless = Headless::new(&args, target, size, start, fps).await?;
+    let n_frames = (duration * fps as f32).ceil() as u32;
+    let mut total = DiffStats::default();
+
+    log::info!("Comparing {n_frames} frames");
+    headless.run(0..n_frames, |ctx, _, time| {
+        let (a, b) = ctx
+            .render_into_compare_buffers()
+            .ok_or_else(|| anyhow::anyhow!("Nothing to compare the shader with"))?;
+        let (a, b) = (a.read(&ctx.device), b.read(&ctx.device));
+        let format = ctx.target_format();
+        let stats = DiffStats::count(
+            &format.decode(&a),
+            &format.decode(&b),
+            args.compare.tolerance,
+        );
+        if stats.differing > 0 {
+            println!("{time:.3}s: {stats}");
+        }
+        total += stats;
+        Ok(())
+    })?;
+
+    println!("Total over {n_frames} frames: {total}");
+    if total.differing > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+async fn bench(
+    args: Args,
+    frames: u32,
+    start: f32,
+    fps: u32,
+    size: PhysicalSize<u32>,
+) -> anyhow::Result<()> {
+    let mut headless = Headless::new(&args, args.target, size, start, fps).await?;
+    let mut frame_times = Vec::with_capacity(frames as _);
+    let mut pass_times = Vec::with_capacity(frames as _);
+    // passes are timed on the CPU without timestamp queries
+    let gpu_timer = headless.ctx.has_gpu_timer();
+
+    log::info!("Benchmarking {frames} frames");
+    // the first frame isn't counted, drivers often finish compiling pipelines there
+    headless.run(0..=frames, |ctx, i, _| {
+        let frame_start = std::time::Instant::now();
+        let passes = if gpu_timer {
+            ctx.render_into_frame_buffer().read(&ctx.device);
+            ctx.gpu_times(true)
+        } else {
+            let (frame, times) = ctx.render_into_frame_buffer_timed();
+            frame.read(&ctx.device);
+            Some(times)
+        };
+        let frame_time = frame_start.elapsed().as_secs_f32() * 1000.0;
+        if i > 0 {
+            frame_times.push(frame_time);
+            pass_times.extend(passes);
+        }
+        Ok(())
+    })?;
+
+    let passes = PASSES
+        .iter()
+        .enumerate()
+        .map(|(i, pass)| {
+            let summary = Summary::of(pass_times.iter().map(|t| t[i]));
+            (pass.to_string(), summary.to_json())
+        })
+        .collect::<serde_json::Map<_, _>>();
+    let report = json!({
+        "shader": args.shader().display().to_string(),
+        "size": [size.width, size.height],
+        "frames": frames,
+        "frame_ms": Summary::of(frame_times).to_json(),
+        "pass_ms": passes,
+        "pass_timer": if gpu_timer { "gpu" } else { "cpu" },
+    });
+    println!("{report}");
+
+    Ok(())
+}
+
+fn export(args: &Args, formats: &[ExportFormat], out_dir: &Path) -> anyhow::Result<()> {
+    let file = args.shader_file(args.shader());
+    let files = crate::export::export(
+        &file,
+        args.mesh.is_some(),
+        args.target.format,
+        formats,
+        out_dir,
+    )?;
+    files.iter().for_each(|f| println!("{}", f.display()));
+
+    Ok(())
+}
+
+// the compared shader comes after the shown ones
+fn watch_shaders(
+    shaders: &[PathBuf],
+    compare: Option<&PathBuf>,
+) -> anyhow::Result<crate::utils::FileWatcher> {
+    let mut files = shaders.to_vec();
+    files.extend(compare.cloned());
+    crate::utils::FileWatcher::new(&files)
+}
+
+// with the options of the command line, compared to its variant if there is one,
+// returns the new window title
+fn show_shader(ctx: &mut WgpuContext, args: &Args, path: &Path) -> String {
+    crate::utils::clear_screen();
+    log::info!("Showing {path:?}");
+    ctx.load_shader(args.shader_file(path));
+    if let Some(file) = args.compare_file(path) {
+        if let Err(e) = ctx.compare_with(file, args.compare.scale) {
+            crate::utils::print(e);
+        }
+    }
+    let title = window_title(path);
+    ctx.window().set_title(&title);
+    title
+}
+
+// PageDown/PageUp, shows the shader after `current` (or before it) and returns the window title
+fn switch_shader(
+    ctx: &mut WgpuContext,
+    args: &Args,
+    shaders: &[PathBuf],
+    current: &mut usize,
+    next: bool,
+) -> String {
+    *current = if next {
+        (*current + 1) % shaders.len()
+    } else {
+        (*current + shaders.len() - 1) % shaders.len()
+    };
+    show_shader(ctx, args, &shaders[*current])
+}
+
+// F5, HDR targets are saved as is as well
+fn save_screenshot(ctx: &mut WgpuContext, channel: &Channel, hdr_format: HdrFormat) {
+    channel.send_msg(Msg::SavePng {
+        frame: ctx.render_into_frame_buffer(),
+        resolution: ctx.resolution,
+    });
+    if ctx.target_format().is_hdr() {
+        channel.send_msg(Msg::SaveHdr {
+            frame: ctx.render_into_target_buffer(),
+            resolution: ctx.resolution,
+            target_format: ctx.target_format(),
+            format: hdr_format,
+        });
+    }
+}
+
+// replies to shader changes with the error of the shader, if it's invalid
+fn shader_status(ctx: &WgpuContext) -> anyhow::Result<serde_json::Value> {
+    match ctx.shader_error() {
+        Some(e) => Err(anyhow::anyhow!("{e}")),
+        None => Ok(json!({ "entry": ctx.fs_entry() })),
+    }
+}
+
+// `TITLE` with the name of the shown shader
+fn window_title(shader: &Path) -> String {
+    let name = shader.file_name().unwrap_or_default().to_string_lossy();
+    format!("{TITLE} - {name}")
+}
+
+async fn draw(args: Args) -> anyhow::Result<()> {
+    let mut shaders = args.shaders.clone();
+    // index of the shown shader
+    let mut current = 0;
+    let mut title = window_title(&shaders[current]);
+    let mut inputs = open_inputs(&args.channels)?;
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
-        .with_title("puss")
+        .with_title(&title)
         .build(&event_loop)
         .expect("create window");
-    let file_watcher = match crate::utils::FileWatcher::new(&shader_path) {
-        Ok(watcher) => watcher,
-        Err(e) => return eprintln!("{e}"),
-    };
-    let channel = crate::utils::Channel::new();
-    let mut ctx = WgpuContext::new(window, shader_path).await;
+    let mut file_watcher = watch_shaders(&shaders, args.compare.shader.as_ref())?;
+    let server = args.ipc.as_ref().map(Server::new).transpose()?;
+    let osc = args
+        .osc
+        .map(|a| Listener::new(a, args.osc_map.as_deref()))
+        .transpose()?;
+    // errors of OSC messages are reported once, controllers send a lot of them
+    let mut osc_errors = std::collections::HashSet::new();
+    let channel = Channel::new();
+    let ctx = WgpuContext::new(
+        window,
+        args.shader_file(&shaders[current]),
+        args.target,
+        args.mesh.as_deref(),
+        &args.adapter,
+    )
+    .await;
+    let mut ctx = ctx?;
+    load_textures(&mut ctx, &args)?;
+    if let Some(file) = args.compare_file(&shaders[current]) {
+        if let Err(e) = ctx.compare_with(file, args.compare.scale) {
+            crate::utils::print(e);
+        }
+    }
     let mut time = crate::utils::Time::new();
     let mut capturing_frames = false;
+    let mut recording_start = 0.0;
+    // frames are stamped with the real time since the recording has started
+    let mut recording_clock = std::time::Instant::now();
+    // lives outside of the context, so it's kept across shader reloads
+    let mut camera = CameraController::new(args.camera);
+    let mut cursor = PhysicalPosition::new(0.0, 0.0);
+    let mut modifiers = ModifiersState::empty();
+    // pixel whose `debug_print` values are printed after the next frame
+    let mut debug_pixel = None;
+    let mut dragging_split = false;
+    let mut stats = FrameStats::default();
+    let mut show_hud = false;
+    // the title is updated only a few times per second, so it's readable
+    let mut title_updated = std::time::Instant::now();
 
     event_loop.run(move |ev, _, cf| {
         *cf = ControlFlow::Poll;
 
         match ev {
             Event::MainEventsCleared => {
-                // TODO: handle errors and other events
-                while let Ok(Ok(event)) = file_watcher.receiver.try_recv() {
-                    if let notify::event::EventKind::Modify(_) = event.kind {
-                        crate::utils::clear_screen();
-                        ctx.rebuild_shader()
+                for request in server.iter().flat_map(Server::requests) {
+                    let result = match &request.command {
+                        Command::Load { path } if !path.is_file() => {
+                            Err(anyhow::anyhow!("{path:?} is not a file"))
+                        }
+                        Command::Load { path } => {
+                            current = match shaders.iter().position(|s| s == path) {
+                                Some(i) => i,
+                                None => {
+                                    shaders.push(path.clone());
+                                    match watch_shaders(&shaders, args.compare.shader.as_ref()) {
+                                        Ok(watcher) => file_watcher = watcher,
+                                        Err(e) => log::error!("{e}"),
+                                    }
+                                    shaders.len() - 1
+                                }
+                            };
+                            title = show_shader(&mut ctx, &args, &shaders[current]);
+                            shader_status(&ctx)
+                        }
+                        Command::Source { code } => {
+                            ctx.load_shader(ShaderFile {
+                                source: Some(code.clone()),
+                                ..args.shader_file(&shaders[current])
+                            });
+                            shader_status(&ctx)
+                        }
+                        Command::Set { name, value } => ctx
+                            .bindings
+                            .uniforms
+                            .set(&ctx.queue, name, &value.to_vec())
+                            .map(|_| serde_json::Value::Null),
+                        Command::Time { value } => {
+                            time.seek(value - time.elapsed);
+                            Ok(serde_json::Value::Null)
+                        }
+                        Command::Pause | Command::Resume => {
+                            if time.paused != matches!(request.command, Command::Pause) {
+                                time.toggle_pause();
+                            }
+                            Ok(serde_json::Value::Null)
+                        }
+                        Command::Screenshot { path } => {
+                            let frame = ctx.render_into_frame_buffer().read(&ctx.device);
+                            crate::capture::write_png(&frame, &ctx.resolution, path)
+                                .map(|_| serde_json::Value::Null)
+                        }
+                        Command::StartRecording if capturing_frames => {
+                            Err(anyhow::anyhow!("Already recording"))
+                        }
+                        Command::StartRecording => {
+                            capturing_frames = true;
+                            recording_start = time.elapsed;
+                            recording_clock = std::time::Instant::now();
+                            log::info!("Recording frames");
+                            Ok(serde_json::Value::Null)
+                        }
+                        Command::StopRecording { .. } if !capturing_frames => {
+                            Err(anyhow::anyhow!("Not recording"))
+                        }
+                        Command::StopRecording { path } => {
+                            capturing_frames = false;
+                            channel.send_msg(Msg::SaveVideo {
+                                rate: RECORDING_FPS,
+                                resolution: ctx.resolution,
+                                format: args.video_format,
+                                start: recording_start,
+                                soundtrack: args.soundtrack.clone(),
+                                path: path.clone(),
+                            });
+                            Ok(serde_json::Value::Null)
+                        }
+                        Command::State => Ok(json!({
+                            "shader": shaders[current],
+                            "entry": ctx.fs_entry(),
+                            "error": ctx.shader_error(),
+                            "time": time.elapsed,
+                            "paused": time.paused,
+                            "recording": capturing_frames,
+                            "resolution": [ctx.resolution.width, ctx.resolution.height],
+                            "uniforms": ctx
+                                .bindings
+                                .uniforms
+                                .values()
+                                .map(|(name, value)| (name.to_owned(), json!(value)))
+                                .collect::<serde_json::Map<_, _>>(),
+                        })),
+                    };
+                    request.reply(result);
+                }
+                // like `Mouse` from cursor events, uniforms are updated as messages come in
+                for (message, target) in osc.iter().flat_map(Listener::messages) {
+                    match target {
+                        Target::Uniform { name, range } => {
+                            let values = crate::osc::scale(&message.args, range);
+                            let set = ctx.bindings.uniforms.set(&ctx.queue, &name, &values);
+                            if let Err(e) = set {
+                                if osc_errors.insert(message.address.clone()) {
+                                    log::warn!("{}: {e}", message.address);
+                                }
+                            }
+                        }
+                        Target::Action(Action::Time) => match message.args.first() {
+                            Some(t) => time.seek(t - time.elapsed),
+                            None => log::warn!("{}: `time` needs a value", message.address),
+                        },
+                        Target::Action(_) if !message.is_trigger() => {}
+                        Target::Action(Action::Pause) => time.toggle_pause(),
+                        Target::Action(action @ (Action::Next | Action::Previous)) => {
+                            let next = matches!(action, Action::Next);
+                            title = switch_shader(&mut ctx, &args, &shaders, &mut current, next);
+                        }
+                        Target::Action(Action::Entry) => ctx.cycle_fs_entry(),
+                        Target::Action(Action::Screenshot) => {
+                            save_screenshot(&mut ctx, &channel, args.hdr_format)
+                        }
                     }
                 }
-                ctx.window.request_redraw();
+                let changed = file_watcher.changed();
+                if changed.contains(&current) || changed.contains(&shaders.len()) {
+                    crate::utils::clear_screen();
+                    ctx.rebuild_shader()
+                }
+                ctx.window().request_redraw();
             }
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == ctx.window.id() => match event {
+            } if window_id == ctx.window().id() => match event {
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                } if camera.key(*key, *state) => {}
                 WindowEvent::KeyboardInput { input, .. } => match input {
                     KeyboardInput {
                         state: ElementState::Pressed,
@@ -55,10 +699,7 @@ async fn draw(shader_path: &str) {
                         state: ElementState::Pressed,
                         virtual_keycode: Some(VirtualKeyCode::F5),
                         ..
-                    } => channel.send_msg(Msg::SavePng {
-                        frame: ctx.render_into_frame_buffer(),
-                        resolution: ctx.resolution,
-                    }),
+                    } => save_screenshot(&mut ctx, &channel, args.hdr_format),
                     KeyboardInput {
                         state: ElementState::Pressed,
                         virtual_keycode: Some(VirtualKeyCode::F6),
@@ -66,6 +707,8 @@ async fn draw(shader_path: &str) {
                     } => {
                         capturing_frames = !capturing_frames;
                         if capturing_frames {
+                            recording_start = time.elapsed;
+                            recording_clock = std::time::Instant::now();
                             log::info!("Recording frames");
                         } else {
                             log::info!("Stoped recording frames");
@@ -75,10 +718,73 @@ async fn draw(shader_path: &str) {
                         state: ElementState::Pressed,
                         virtual_keycode: Some(VirtualKeyCode::F7),
                         ..
-                    } if !capturing_frames => channel.send_msg(Msg::SaveMp4 {
-                        rate: time.delta as _,
+                    } if !capturing_frames => channel.send_msg(Msg::SaveVideo {
+                        rate: RECORDING_FPS,
                         resolution: ctx.resolution,
+                        format: args.video_format,
+                        start: recording_start,
+                        soundtrack: args.soundtrack.clone(),
+                        path: None,
                     }),
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Space),
+                        ..
+                    } => time.toggle_pause(),
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Left),
+                        ..
+                    } => time.seek(-SEEK_STEP),
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Right),
+                        ..
+                    } => time.seek(SEEK_STEP),
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode:
+                            Some(key @ (VirtualKeyCode::PageDown | VirtualKeyCode::PageUp)),
+                        ..
+                    } => {
+                        let next = *key == VirtualKeyCode::PageDown;
+                        title = switch_shader(&mut ctx, &args, &shaders, &mut current, next);
+                    }
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::C),
+                        ..
+                    } => crate::utils::print(camera.camera.as_args()),
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::G),
+                        ..
+                    } => ctx.cycle_attachment(),
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::E),
+                        ..
+                    } => ctx.cycle_fs_entry(),
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::V),
+                        ..
+                    } => ctx.toggle_compare_view(),
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::N),
+                        ..
+                    } => ctx.toggle_diagnostics(),
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::H),
+                        ..
+                    } => {
+                        show_hud = !show_hud;
+                        if !show_hud {
+                            ctx.window().set_title(&title);
+                        }
+                    }
                     _ => {}
                 },
                 WindowEvent::CloseRequested => {
@@ -90,43 +796,140 @@ async fn draw(shader_path: &str) {
                     ctx.resize(new_inner_size)
                 }
                 WindowEvent::CursorMoved { position, .. } => {
-                    let pos = position.cast::<f32>();
-                    ctx.bindings.mouse.update(&ctx.queue, [pos.x, pos.y])
+                    cursor = position.cast::<f32>();
+                    if dragging_split {
+                        ctx.move_split_line(cursor.x);
+                    }
+                    camera.cursor_moved(cursor.x, cursor.y);
+                    ctx.bindings.mouse.update(&ctx.queue, [cursor.x, cursor.y])
+                }
+                WindowEvent::ModifiersChanged(new) => {
+                    // the title shows pixel values only while Shift is held
+                    if modifiers.shift() && !new.shift() {
+                        ctx.window().set_title(&title);
+                    }
+                    modifiers = *new;
+                }
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button: MouseButton::Right,
+                    ..
+                } => {
+                    if let Some(value) = inspect_pixel(&ctx, cursor) {
+                        crate::utils::print(value);
+                        debug_pixel = Some([cursor.x as u32, cursor.y as u32]);
+                    }
                 }
+                // the split line is dragged instead of the camera if it's grabbed
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button: MouseButton::Left,
+                    ..
+                } if ctx
+                    .split_line()
+                    .is_some_and(|x| (x - cursor.x).abs() <= SPLIT_GRAB) =>
+                {
+                    dragging_split = true
+                }
+                WindowEvent::MouseInput { state, button, .. } => {
+                    if *button == MouseButton::Left && *state == ElementState::Released {
+                        dragging_split = false;
+                    }
+                    camera.mouse_button(*button, *state)
+                }
+                WindowEvent::MouseWheel { delta, .. } => camera.scroll(*delta),
                 _ => {}
             },
-            Event::RedrawRequested(window_id) if window_id == ctx.window.id() => {
+            Event::RedrawRequested(window_id) if window_id == ctx.window().id() => {
+                time.update();
                 let q = &ctx.queue;
                 let res = ctx.resolution.cast::<f32>();
-                ctx.bindings
-                    .time
-                    .update(q, time.start.elapsed().as_secs_f32());
+                ctx.bindings.time.update(q, time.elapsed);
                 ctx.bindings.resolution.update(q, [res.width, res.height]);
-                time.update();
+
+                update_inputs(&mut ctx, &mut inputs, time.elapsed, true, time.paused);
+                camera.update(time.frame_time);
+                update_transforms(&mut ctx, &camera.camera);
+                if debug_pixel.is_some() {
+                    ctx.bindings
+                        .debug
+                        .update(&ctx.queue, &DebugOutput::select(debug_pixel));
+                }
 
                 if capturing_frames {
-                    channel.send_msg(Msg::ExtractData(ctx.render_into_frame_buffer()));
+                    channel.send_msg(Msg::ExtractData {
+                        frame: ctx.render_into_frame_buffer(),
+                        time: recording_clock.elapsed().as_secs_f32(),
+                    });
                 }
 
-                match ctx.render_frame() {
-                    Ok(_) => {}
-                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                stats.push_frame(time.frame_time * 1000.0);
+                // wgpu panics when the device is lost during a submit or a poll
+                let frame = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                    if let Some(times) = ctx.gpu_times(false) {
+                        stats.push_gpu(times);
+                    }
+                    ctx.render_frame(show_hud.then_some(&stats))
+                }));
+                let lost = match frame {
+                    Ok(Ok(_)) => false,
+                    Ok(Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated)) => {
                         log::info!("Resizing window");
-                        ctx.resize(&ctx.window.inner_size())
+                        ctx.resize(&ctx.window().inner_size());
+                        false
+                    }
+                    Ok(Err(wgpu::SurfaceError::Timeout)) => {
+                        log::warn!("Surface timeout");
+                        false
                     }
-                    Err(wgpu::SurfaceError::OutOfMemory) => {
-                        log::error!("Out of memory, exiting");
-                        *cf = ControlFlow::Exit;
+                    Ok(Err(wgpu::SurfaceError::OutOfMemory)) => true,
+                    Err(_) if ctx.probe_lost() => true,
+                    Err(panic) => std::panic::resume_unwind(panic),
+                };
+                if lost || ctx.is_lost() {
+                    if let Err(e) = ctx.recover() {
+                        log::error!("Failed to recreate the device: {e}, exiting");
+                        *cf = ControlFlow::ExitWithCode(1);
+                        return;
                     }
-                    Err(wgpu::SurfaceError::Timeout) => log::warn!("Surface timeout"),
+                    if let Err(e) = load_textures(&mut ctx, &args) {
+                        log::error!("{e}");
+                    }
+                    debug_pixel = None;
+                    return;
+                }
+
+                if debug_pixel.take().is_some() {
+                    print_debug_output(&ctx);
+                    ctx.bindings
+                        .debug
+                        .update(&ctx.queue, &DebugOutput::select(None));
+                }
+
+                if modifiers.shift() {
+                    if let Some(value) = inspect_pixel(&ctx, cursor) {
+                        ctx.window().set_title(&format!("{title} {value}"));
+                    }
+                } else if show_hud && title_updated.elapsed().as_secs_f32() > 0.5 {
+                    ctx.window()
+                        .set_title(&format!("{title} {}", stats.summary()));
+                    title_updated = std::time::Instant::now();
                 }
             }
-            Event::RedrawEventsCleared => ctx.window.request_redraw(),
+            Event::RedrawEventsCleared => ctx.window().request_redraw(),
             _ => {}
         }
     })
 }
 
+// for errors the modes can't go on after, with their context
+fn exit_on_error(result: anyhow::Result<()>) {
+    if let Err(e) = result {
+        eprintln!("ERROR: {e:#}");
+        std::process::exit(1);
+    }
+}
+
 fn main() {
     tokio::runtime::Builder::new_multi_thread()
         .build()
@@ -134,11 +937,43 @@ fn main() {
         .block_on(async {
             env_logger::init();
             crate::utils::clear_screen();
-            if let Some(shader) = std::env::args().nth(1) {
-                draw(&shader).await;
-            } else {
-                eprintln!("ERROR: Shader path was not specifyed");
-                std::process::exit(1);
+            match Args::parse() {
+                Ok(args) => match args.mode {
+                    Mode::View => exit_on_error(draw(args).await),
+                    Mode::Render {
+                        start,
+                        duration,
+                        fps,
+                        size,
+                    } => exit_on_error(render(args, start, duration, fps, size).await),
+                    Mode::Bench {
+                        frames,
+                        start,
+                        fps,
+                        size,
+                    } => exit_on_error(bench(args, frames, start, fps, size).await),
+                    Mode::Check {
+                        start,
+                        duration,
+                        fps,
+                        size,
+                    } => exit_on_error(check(args, start, duration, fps, size).await),
+                    Mode::Compare {
+                        start,
+                        duration,
+                        fps,
+                        size,
+                    } => exit_on_error(compare(args, start, duration, fps, size).await),
+                    Mode::Export {
+                        ref formats,
+                        ref out_dir,
+                    } => exit_on_error(export(&args, formats, out_dir)),
+                    Mode::Info => crate::adapter::print_adapters(&args.adapter),
+                },
+                Err(e) => {
+                    eprintln!("ERROR: {e}");
+                    std::process::exit(1);
+                }
             }
         })
 }
@@ -0,0 +1,180 @@
+use anyhow::Context;
+use glam::{Mat4, Vec3};
+use std::path::Path;
+use wgpu::util::DeviceExt;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+impl Vertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2];
+
+    pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as _,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Indexed triangle list uploaded to the GPU.
+pub struct Mesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub index_count: u32,
+    /// Scales and centers the mesh into the -1..1 cube.
+    pub model_matrix: Mat4,
+}
+
+impl Mesh {
+    /// Loads all triangles of an OBJ or glTF (.gltf/.glb) file.
+    pub fn load(device: &wgpu::Device, path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let (mut vertices, indices) = match path.extension().and_then(|e| e.to_str()) {
+            Some("obj") => load_obj(path)?,
+            Some("gltf" | "glb") => load_gltf(path)?,
+            _ => anyhow::bail!("{path:?}: unsupported mesh format, expected .obj, .gltf or .glb"),
+        };
+        if indices.is_empty() {
+            anyhow::bail!("{path:?}: no triangles found");
+        }
+        if vertices.iter().all(|v| v.normal == [0.0; 3]) {
+            compute_normals(&mut vertices, &indices);
+        }
+
+        let (min, max) = vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), v| {
+                let p = Vec3::from(v.position);
+                (min.min(p), max.max(p))
+            },
+        );
+        let extent = (max - min).max_element().max(f32::EPSILON);
+        let model_matrix = Mat4::from_scale(Vec3::splat(2.0 / extent))
+            * Mat4::from_translation(-(min + max) * 0.5);
+
+        log::info!(
+            "Loaded mesh {path:?}: {n_vertices} vertices, {n_triangles} triangles",
+            n_vertices = vertices.len(),
+            n_triangles = indices.len() / 3,
+        );
+
+        Ok(Self {
+            vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("vertices"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
+            index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("indices"),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }),
+            index_count: indices.len() as _,
+            model_matrix,
+        })
+    }
+}
+
+fn load_obj(path: &Path) -> anyhow::Result<(Vec<Vertex>, Vec<u32>)> {
+    let (models, _) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)
+        .with_context(|| format!("Failed to load {path:?}"))?;
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+
+    for model in models {
+        let mesh = model.mesh;
+        let offset = vertices.len() as u32;
+        vertices.extend((0..mesh.positions.len() / 3).map(|i| Vertex {
+            position: [0, 1, 2].map(|c| mesh.positions[i * 3 + c]),
+            normal: [0, 1, 2].map(|c| mesh.normals.get(i * 3 + c).copied().unwrap_or_default()),
+            // OBJ has the origin of the texture at the bottom
+            uv: [
+                mesh.texcoords.get(i * 2).copied().unwrap_or_default(),
+                1.0 - mesh.texcoords.get(i * 2 + 1).copied().unwrap_or(1.0),
+            ],
+        }));
+        indices.extend(mesh.indices.iter().map(|i| i + offset));
+    }
+
+    Ok((vertices, indices))
+}
+
+fn load_gltf(path: &Path) -> anyhow::Result<(Vec<Vertex>, Vec<u32>)> {
+    let (document, buffers, _) =
+        gltf::import(path).with_context(|| format!("Failed to load {path:?}"))?;
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .with_context(|| format!("{path:?}: no scenes found"))?;
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut nodes = scene
+        .nodes()
+        .map(|n| (n, Mat4::IDENTITY))
+        .collect::<Vec<_>>();
+
+    while let Some((node, parent)) = nodes.pop() {
+        let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+        let normal_transform = transform.inverse().transpose();
+        nodes.extend(node.children().map(|c| (c, transform)));
+
+        let Some(mesh) = node.mesh() else {
+            continue;
+        };
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+            let reader = primitive.reader(|b| Some(&buffers[b.index()]));
+            let Some(positions) = reader.read_positions() else {
+                continue;
+            };
+            let offset = vertices.len() as u32;
+            let mut normals = reader.read_normals();
+            let mut uvs = reader.read_tex_coords(0).map(|t| t.into_f32());
+
+            for position in positions {
+                let normal = normals.as_mut().and_then(|n| n.next()).unwrap_or_default();
+                vertices.push(Vertex {
+                    position: transform.transform_point3(position.into()).into(),
+                    normal: normal_transform
+                        .transform_vector3(normal.into())
+                        .normalize_or_zero()
+                        .into(),
+                    uv: uvs.as_mut().and_then(|t| t.next()).unwrap_or_default(),
+                });
+            }
+
+            match reader.read_indices() {
+                Some(idx) => indices.extend(idx.into_u32().map(|i| i + offset)),
+                None => indices.extend(offset..vertices.len() as u32),
+            }
+        }
+    }
+
+    Ok((vertices, indices))
+}
+
+// smooth normals for meshes that come without them
+fn compute_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![Vec3::ZERO; vertices.len()];
+    for tri in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(vertices[tri[i] as usize].position));
+        // not normalized, so bigger faces have more weight
+        let face_normal = (b - a).cross(c - a);
+        for i in tri {
+            normals[*i as usize] += face_normal;
+        }
+    }
+    for (v, n) in vertices.iter_mut().zip(normals) {
+        v.normal = n.normalize_or_zero().into();
+    }
+}
@@ -0,0 +1,431 @@
+use anyhow::Context;
+use std::{
+    net::{SocketAddr, UdpSocket},
+    path::Path,
+    sync::mpsc,
+};
+
+// prefix of addresses that set a uniform without a mapping, `/u/speed 0.5`
+const UNIFORM_PREFIX: &str = "/u/";
+
+/// What an OSC address controls.
+#[derive(Clone, Debug)]
+pub enum Target {
+    /// Values in 0..1 are scaled into the range, if there is one.
+    Uniform {
+        name: String,
+        range: Option<(f32, f32)>,
+    },
+    Action(Action),
+}
+
+/// Actions are triggered by messages without arguments or with a non-zero first one,
+/// so buttons trigger them when they are pressed and not again when they are released.
+#[derive(Clone, Copy, Debug)]
+pub enum Action {
+    /// Pauses or resumes `Time`.
+    Pause,
+    /// Shows the next shader.
+    Next,
+    /// Shows the previous shader.
+    Previous,
+    /// Renders the next fragment entry.
+    Entry,
+    Screenshot,
+    /// Sets `Time` to the first argument, it's always applied.
+    Time,
+}
+
+impl std::str::FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pause" => Ok(Self::Pause),
+            "next" => Ok(Self::Next),
+            "previous" => Ok(Self::Previous),
+            "entry" => Ok(Self::Entry),
+            "screenshot" => Ok(Self::Screenshot),
+            "time" => Ok(Self::Time),
+            _ => Err(anyhow::anyhow!(
+                "Unknown action `{s}`, expected pause, next, previous, entry, screenshot or time"
+            )),
+        }
+    }
+}
+
+/// A decoded message, numbers and booleans are converted to floats, other arguments are dropped.
+#[derive(Debug)]
+pub struct Message {
+    pub address: String,
+    pub args: Vec<f32>,
+}
+
+impl Message {
+    /// Whether the message triggers an [`Action`].
+    pub fn is_trigger(&self) -> bool {
+        self.args.first().is_none_or(|v| *v != 0.0)
+    }
+}
+
+/// Receives OSC packets on a UDP socket in a background thread.
+pub struct Listener {
+    receiver: mpsc::Receiver<Message>,
+    mappings: Vec<(String, Target)>,
+}
+
+impl Listener {
+    /// `mappings` is a file of `<address> <target>` lines, see [`parse_mappings`].
+    pub fn new(address: SocketAddr, mappings: Option<&Path>) -> anyhow::Result<Self> {
+        let mappings = match mappings {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {path:?}"))?;
+                parse_mappings(&text).with_context(|| format!("Invalid OSC mappings {path:?}"))?
+            }
+            None => Vec::new(),
+        };
+        let socket = UdpSocket::bind(address)
+            .with_context(|| format!("Failed to listen for OSC on {address}"))?;
+        log::info!("Listening for OSC on {address}");
+
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            // the largest UDP payload
+            let mut buf = vec![0; 65536];
+            loop {
+                let len = match socket.recv(&mut buf) {
+                    Ok(len) => len,
+                    Err(e) => {
+                        log::error!("OSC: {e}");
+                        continue;
+                    }
+                };
+                match decode(&buf[..len]) {
+                    Ok(messages) => {
+                        for message in messages {
+                            if sender.send(message).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(e) => log::warn!("Invalid OSC packet: {e}"),
+                }
+            }
+        });
+
+        Ok(Self { receiver, mappings })
+    }
+
+    /// Messages received since the last call with what they control,
+    /// unmapped ones are logged and skipped.
+    pub fn messages(&self) -> Vec<(Message, Target)> {
+        self.receiver
+            .try_iter()
+            .filter_map(|message| match self.target(&message.address) {
+                Some(target) => Some((message, target)),
+                None => {
+                    log::debug!("Unmapped OSC address {}", message.address);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    // mappings take precedence over the `/u/` prefix
+    fn target(&self, address: &str) -> Option<Target> {
+        let mapped = self.mappings.iter().find(|(a, _)| a == address);
+        match mapped {
+            Some((_, target)) => Some(target.clone()),
+            None => address
+                .strip_prefix(UNIFORM_PREFIX)
+                .filter(|name| !name.is_empty() && !name.contains('/'))
+                .map(|name| Target::Uniform {
+                    name: name.to_owned(),
+                    range: None,
+                }),
+        }
+    }
+}
+
+/// Scales values of a [`Target::Uniform`] into its range.
+pub fn scale(values: &[f32], range: Option<(f32, f32)>) -> Vec<f32> {
+    match range {
+        Some((min, max)) => values.iter().map(|v| min + v * (max - min)).collect(),
+        None => values.to_vec(),
+    }
+}
+
+/// Lines like `/1/fader1 uniform speed 0 4` (the range is optional) or `/1/push1 next`,
+/// `#` starts a comment.
+pub fn parse_mappings(text: &str) -> anyhow::Result<Vec<(String, Target)>> {
+    let mut mappings = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let parts = line.split_whitespace().collect::<Vec<_>>();
+        let target = match parts[..] {
+            [] => continue,
+            [_, "uniform", name] => Target::Uniform {
+                name: name.to_owned(),
+                range: None,
+            },
+            [_, "uniform", name, min, max] => {
+                let range = min.parse().ok().zip(max.parse().ok());
+                let range = range
+                    .with_context(|| format!("line {}: invalid range `{min} {max}`", i + 1))?;
+                Target::Uniform {
+                    name: name.to_owned(),
+                    range: Some(range),
+                }
+            }
+            [_, action] if action != "uniform" => {
+                Target::Action(action.parse().with_context(|| format!("line {}", i + 1))?)
+            }
+            _ => anyhow::bail!(
+                "line {}: expected `<address> uniform <name> [<min> <max>]` or `<address> <action>`",
+                i + 1
+            ),
+        };
+        if !parts[0].starts_with('/') {
+            anyhow::bail!(
+                "line {}: address `{}` doesn't start with `/`",
+                i + 1,
+                parts[0]
+            );
+        }
+        mappings.push((parts[0].to_owned(), target));
+    }
+
+    Ok(mappings)
+}
+
+/// Messages of a packet, the ones of bundles are in order and their time tags are ignored.
+pub fn decode(packet: &[u8]) -> anyhow::Result<Vec<Message>> {
+    let mut messages = Vec::new();
+    decode_into(packet, &mut messages)?;
+    Ok(messages)
+}
+
+fn decode_into(packet: &[u8], messages: &mut Vec<Message>) -> anyhow::Result<()> {
+    let mut reader = Reader(packet);
+    let address = reader.string()?;
+    if address == "#bundle" {
+        // time tag
+        reader.take(8)?;
+        while !reader.0.is_empty() {
+            let len = reader.int()?;
+            let len = usize::try_from(len).context("negative bundle element size")?;
+            decode_into(reader.take(len)?, messages)?;
+        }
+        return Ok(());
+    }
+    if !address.starts_with('/') {
+        anyhow::bail!("address `{address}` doesn't start with `/`");
+    }
+
+    // old implementations may omit the type tags
+    let tags = if reader.0.is_empty() {
+        String::new()
+    } else {
+        reader.string()?
+    };
+    let Some(tags) = tags.strip_prefix(',').or(tags.is_empty().then_some("")) else {
+        anyhow::bail!("type tags `{tags}` don't start with `,`");
+    };
+    let mut args = Vec::new();
+    for tag in tags.chars() {
+        match tag {
+            'f' => args.push(f32::from_bits(reader.int()? as u32)),
+            'i' => args.push(reader.int()? as f32),
+            'd' => args.push(f64::from_bits(reader.long()?) as f32),
+            'h' => args.push(reader.long()? as i64 as f32),
+            'T' => args.push(1.0),
+            'F' => args.push(0.0),
+            's' | 'S' => {
+                reader.string()?;
+            }
+            'b' => {
+                let len = usize::try_from(reader.int()?).context("negative blob size")?;
+                reader.take(len.next_multiple_of(4))?;
+            }
+            't' => {
+                reader.take(8)?;
+            }
+            'c' | 'r' | 'm' => {
+                reader.take(4)?;
+            }
+            'N' | 'I' | '[' | ']' => {}
+            _ => anyhow::bail!("unsupported type tag `{tag}`"),
+        }
+    }
+    messages.push(Message { address, args });
+
+    Ok(())
+}
+
+// big endian values padded to 4 bytes
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if len > self.0.len() {
+            anyhow::bail!("packet is truncated");
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn int(&mut self) -> anyhow::Result<i32> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_be_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    fn long(&mut self) -> anyhow::Result<u64> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_be_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    // null terminated, then padded
+    fn string(&mut self) -> anyhow::Result<String> {
+        let len = self
+            .0
+            .iter()
+            .position(|b| *b == 0)
+            .context("string isn't terminated")?;
+        let bytes = self.take((len + 1).next_multiple_of(4))?;
+        Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // null terminated and padded to 4 bytes
+    fn string(s: &str) -> Vec<u8> {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.resize((s.len() + 1).next_multiple_of(4), 0);
+        bytes
+    }
+
+    fn message(address: &str, tags: &str, args: &[&[u8]]) -> Vec<u8> {
+        let mut packet = string(address);
+        packet.extend(string(tags));
+        args.iter().for_each(|arg| packet.extend(*arg));
+        packet
+    }
+
+    fn bundle(elements: &[Vec<u8>]) -> Vec<u8> {
+        let mut packet = string("#bundle");
+        // immediately
+        packet.extend(1u64.to_be_bytes());
+        for element in elements {
+            packet.extend((element.len() as i32).to_be_bytes());
+            packet.extend(element);
+        }
+        packet
+    }
+
+    #[test]
+    fn decodes_a_message() {
+        let packet = message(
+            "/u/color",
+            ",fisTF",
+            &[
+                &0.25f32.to_be_bytes(),
+                &3i32.to_be_bytes(),
+                &string("ignored"),
+            ],
+        );
+        let messages = decode(&packet).unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].address, "/u/color");
+        assert_eq!(messages[0].args, [0.25, 3.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn decodes_a_nested_bundle_in_order() {
+        let packet = bundle(&[
+            message("/1/push1", ",f", &[&1f32.to_be_bytes()]),
+            bundle(&[message("/1/push1", ",f", &[&0f32.to_be_bytes()])]),
+            message("/1/push2", "", &[]),
+        ]);
+        let messages = decode(&packet).unwrap();
+
+        let decoded = messages
+            .iter()
+            .map(|m| (m.address.as_str(), m.is_trigger()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            decoded,
+            [("/1/push1", true), ("/1/push1", false), ("/1/push2", true)]
+        );
+    }
+
+    #[test]
+    fn rejects_a_truncated_packet() {
+        let packet = message("/u/speed", ",f", &[&0.5f32.to_be_bytes()]);
+        for len in [3, 10, packet.len() - 1] {
+            let err = decode(&packet[..len]).unwrap_err().to_string();
+            assert!(
+                err == "packet is truncated" || err == "string isn't terminated",
+                "{len}: {err}"
+            );
+        }
+        // an element of a bundle that is longer than the rest of the packet
+        let mut packet = bundle(&[packet]);
+        packet.truncate(packet.len() - 4);
+        assert_eq!(
+            decode(&packet).unwrap_err().to_string(),
+            "packet is truncated"
+        );
+    }
+
+    #[test]
+    fn rejects_an_unknown_tag() {
+        let packet = message("/u/speed", ",x", &[&0i32.to_be_bytes()]);
+
+        assert_eq!(
+            decode(&packet).unwrap_err().to_string(),
+            "unsupported type tag `x`"
+        );
+    }
+
+    #[test]
+    fn parses_mappings() {
+        let mappings = parse_mappings(
+            "# TouchOSC layout
+/1/fader1 uniform speed 0 4
+/1/fader2 uniform hue # in 0..1
+
+/1/push1 next
+",
+        )
+        .unwrap();
+
+        assert_eq!(mappings.len(), 3);
+        assert!(matches!(
+            &mappings[0],
+            (address, Target::Uniform { name, range: Some((0.0, 4.0)) })
+                if address == "/1/fader1" && name == "speed"
+        ));
+        assert!(matches!(
+            &mappings[1],
+            (_, Target::Uniform { name, range: None }) if name == "hue"
+        ));
+        assert!(matches!(&mappings[2], (_, Target::Action(Action::Next))));
+        assert_eq!(scale(&[0.5], Some((0.0, 4.0))), [2.0]);
+    }
+
+    #[test]
+    fn rejects_invalid_mappings() {
+        let error = |text| format!("{:#}", parse_mappings(text).unwrap_err());
+
+        assert!(error("/a next\n/b jump").starts_with("line 2: Unknown action `jump`"));
+        assert!(error("/a uniform speed 0 high").starts_with("line 1: invalid range"));
+        assert!(error("/a uniform").starts_with("line 1: expected"));
+        assert!(error("a next").starts_with("line 1: address `a`"));
+    }
+}
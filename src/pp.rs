@@ -1,13 +1,59 @@
-use crate::bind::ShaderBindings;
+use crate::bind::{BindingDecls, BINDING_ALIASES, DEBUG_ENTRIES};
 use crate::ctx::{FS_ENTRY, VS_ENTRY};
-use naga::{front::wgsl, valid};
-use std::path::Path;
+use crate::target::TargetFormat;
+use codespan_reporting::{
+    diagnostic::{Diagnostic, Label},
+    files::SimpleFiles,
+    term,
+};
+use naga::{
+    front::{spv, wgsl},
+    valid,
+};
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
-pub struct ShaderSource(String);
+/// A shader file, the defines it's preprocessed with and the entry points to use.
+#[derive(Clone)]
+pub struct ShaderFile {
+    pub path: PathBuf,
+    /// `-D` defines, as if they were at the top of the file.
+    pub defines: Vec<(String, String)>,
+    /// `fs_main` if `None`, or the first fragment entry of SPIR-V shaders.
+    pub fs_entry: Option<String>,
+    /// `vs_main` if `None`, or the first vertex entry of SPIR-V shaders.
+    /// Without one the vertex stage is generated.
+    pub vs_entry: Option<String>,
+    /// `--uniform` declarations, the name and default value of each member of `Uniforms`.
+    pub uniforms: Vec<(String, Vec<f32>)>,
+    /// WGSL code rendered instead of the contents of `path`, which still names it in errors.
+    pub source: Option<String>,
+}
+
+pub struct ShaderSource {
+    source: String,
+    /// The validated module, for SPIR-V shaders without the generated vertex stage.
+    module: naga::Module,
+    info: valid::ModuleInfo,
+    /// A SPIR-V shader with its bindings moved to the global ones,
+    /// `source` then only has what the generated vertex stage needs.
+    spirv: bool,
+    /// Number of color attachments written by the fragment entry.
+    pub outputs: u32,
+    /// Whether the shader uses `debug_print`, the `DebugOutput` buffer is only bound then.
+    pub debug: bool,
+    pub vs_entry: String,
+    pub fs_entry: String,
+    /// All fragment entries of the shader, in order.
+    pub fs_entries: Vec<String>,
+}
 
 impl Default for ShaderSource {
     fn default() -> Self {
-        Self(format!(
+        let source = format!(
             r#" {vertex_main}
 
         @fragment
@@ -16,44 +62,624 @@ impl Default for ShaderSource {
         }}
         "#,
             vertex_main = generate_vertex_main(),
-        ))
+        );
+        let module = wgsl::parse_str(&source).expect("default shader");
+        let info =
+            valid::Validator::new(valid::ValidationFlags::all(), valid::Capabilities::empty())
+                .validate(&module)
+                .expect("default shader");
+
+        Self {
+            source,
+            module,
+            info,
+            spirv: false,
+            outputs: 1,
+            debug: false,
+            vs_entry: VS_ENTRY.to_owned(),
+            fs_entry: FS_ENTRY.to_owned(),
+            fs_entries: vec![FS_ENTRY.to_owned()],
+        }
     }
 }
 
 impl ShaderSource {
-    pub fn validate(path: impl AsRef<Path>, bindings: &ShaderBindings) -> Result<Self, String> {
+    /// With `mesh` set the generated vertex stage transforms the loaded mesh,
+    /// otherwise it draws a fullscreen triangle.
+    pub fn validate(
+        file: &ShaderFile,
+        bindings: &BindingDecls,
+        mesh: bool,
+        format: TargetFormat,
+    ) -> Result<Self, String> {
         // TODO: catch redefenition of function
-        let path = path.as_ref();
-        let loaded = std::fs::read_to_string(path).map_err(|e| format!("{path:?}: {e}"))?;
-        let mut source = loaded + &bindings.as_wgsl_string();
-        let module = wgsl::parse_str(&source)
-            .map_err(|e| e.emit_to_string_with_path(&source, path.to_str().unwrap()))?;
+        let path = file.path.as_path();
+        if file.source.is_none() && path.extension().is_some_and(|ext| ext == "spv") {
+            return Self::validate_spirv(file, bindings, mesh, format);
+        }
+        let loaded = match file.source {
+            Some(ref source) => source.clone(),
+            None => std::fs::read_to_string(path).map_err(|e| format!("{path:?}: {e}"))?,
+        };
+        let (mut map, mut module, mut info) = parse_wgsl(path, loaded, &file.defines, bindings)?;
+
+        let fs_entry = file.fs_entry.as_deref().unwrap_or(FS_ENTRY);
+        let fs =
+            find_entry(&module, naga::ShaderStage::Fragment, Some(fs_entry)).ok_or_else(|| {
+                missing_entry(path, &module, naga::ShaderStage::Fragment, Some(fs_entry))
+            })?;
+        let outputs = fragment_outputs(&module, fs, path, format)?;
+
+        let vs_entry = file.vs_entry.as_deref().unwrap_or(VS_ENTRY);
+        if find_entry(&module, naga::ShaderStage::Vertex, Some(vs_entry)).is_none() {
+            // only `vs_main` is generated, a selected entry has to exist
+            if file.vs_entry.is_some() {
+                return Err(missing_entry(
+                    path,
+                    &module,
+                    naga::ShaderStage::Vertex,
+                    Some(vs_entry),
+                ));
+            }
+            map.push("<vertex main>", vertex_main(mesh));
+            (module, info) = map.parse()?;
+        }
+
+        Ok(Self {
+            source: map.source,
+            fs_entries: entry_names(&module, naga::ShaderStage::Fragment),
+            debug: declares_debug_output(&module),
+            module,
+            info,
+            spirv: false,
+            outputs,
+            vs_entry: vs_entry.to_owned(),
+            fs_entry: fs_entry.to_owned(),
+        })
+    }
+
+    // the globals are matched with the bindings by name, whatever sets and bindings
+    // the compiler gave them, a missing vertex entry is generated in WGSL
+    fn validate_spirv(
+        file: &ShaderFile,
+        bindings: &BindingDecls,
+        mesh: bool,
+        format: TargetFormat,
+    ) -> Result<Self, String> {
+        let path = file.path.as_path();
+        let bytes = std::fs::read(path).map_err(|e| format!("{path:?}: {e}"))?;
+        let mut module = spv::parse_u8_slice(&bytes, &spv::Options::default())
+            .map_err(|e| format!("{path:?} parsing error: {e}"))?;
+        bind_globals(&mut module, bindings).map_err(|e| format!("{path:?}: {e}"))?;
+
+        let info =
+            valid::Validator::new(valid::ValidationFlags::all(), valid::Capabilities::empty())
+                .validate(&module)
+                .map_err(|e| {
+                    let mut msg = format!("{path:?} validation error: {}", e.as_inner());
+                    for cause in causes(e.as_inner()) {
+                        msg += &format!("\n  = {cause}");
+                    }
+                    msg
+                })?;
+        let stage = naga::ShaderStage::Fragment;
+        let fs = find_entry(&module, stage, file.fs_entry.as_deref())
+            .ok_or_else(|| missing_entry(path, &module, stage, file.fs_entry.as_deref()))?;
+        let outputs = fragment_outputs(&module, fs, path, format)?;
+        let fs_entry = fs.name.clone();
+
+        let debug = declares_debug_output(&module);
+        let mut source = bindings.as_wgsl_string(debug);
+        if debug {
+            source += &generate_debug_print();
+        }
+        let stage = naga::ShaderStage::Vertex;
+        let vs_entry = match find_entry(&module, stage, file.vs_entry.as_deref()) {
+            Some(vs) => vs.name.clone(),
+            None if file.vs_entry.is_some() => {
+                return Err(missing_entry(
+                    path,
+                    &module,
+                    stage,
+                    file.vs_entry.as_deref(),
+                ))
+            }
+            None => {
+                source += &vertex_main(mesh);
+                VS_ENTRY.to_owned()
+            }
+        };
+
+        Ok(Self {
+            source,
+            fs_entries: entry_names(&module, naga::ShaderStage::Fragment),
+            module,
+            info,
+            spirv: true,
+            outputs,
+            debug,
+            vs_entry,
+            fs_entry,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// The module of a SPIR-V shader, its vertex entry is in `as_str` if it has none.
+    pub fn spirv(&self) -> Option<&naga::Module> {
+        self.spirv.then_some(&self.module)
+    }
+
+    /// The final module with its validation info, for naga's backends.
+    pub fn module(&self) -> (&naga::Module, &valid::ModuleInfo) {
+        (&self.module, &self.info)
+    }
+}
+
+// `#ifdef` or `#ifndef` that wasn't closed yet
+struct Branch {
+    line: usize,
+    active: bool,
+    has_else: bool,
+}
+
+// `#define NAME [VALUE]`, `#undef NAME`, `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif`,
+// defined names are replaced with their values (expanded when they are defined).
+// Directives and skipped lines are left empty, so line numbers don't change.
+// Lines in block comments are never directives.
+fn preprocess(
+    source: &str,
+    path: &Path,
+    defines: &[(String, String)],
+) -> Result<(String, LineMap), String> {
+    let mut defines = defines.iter().cloned().collect::<HashMap<_, _>>();
+    let mut branches = Vec::<Branch>::new();
+    let mut out = String::with_capacity(source.len());
+    let mut lines = Vec::new();
+    let mut file_start = 0;
+    // nesting of block comments, they are only tracked in active lines
+    let mut comment = 0;
+
+    for (i, raw) in source.split_inclusive('\n').enumerate() {
+        let line = raw.strip_suffix('\n').unwrap_or(raw);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let mut mapped = Line {
+            start: out.len(),
+            file_start,
+            substitutions: Vec::new(),
+        };
+        file_start += raw.len();
+        let error = |msg: &str| format!("{}:{}: {msg}", path.display(), i + 1);
+        let active = branches.iter().all(|b| b.active);
+        let directive = line.trim_start().strip_prefix('#').filter(|_| comment == 0);
+        let Some(directive) = directive else {
+            if active {
+                let (substituted, substitutions) = substitute(line, &defines, &mut comment);
+                out += &substituted;
+                mapped.substitutions = substitutions;
+            }
+            out.push('\n');
+            lines.push(mapped);
+            continue;
+        };
+        lines.push(mapped);
+
+        // `#endif // NAME`
+        let directive = directive.split("//").next().unwrap_or_default().trim();
+        let mut parts = directive.splitn(3, char::is_whitespace);
+        let keyword = parts.next().unwrap_or_default();
+        let name = parts.next();
+        let value = parts.next().unwrap_or_default().trim();
+        match (keyword, name) {
+            ("define", Some(name)) if active => {
+                let value = if value.is_empty() { "1" } else { value };
+                let (value, _) = substitute(value, &defines, &mut 0);
+                defines.insert(name.to_owned(), value);
+            }
+            ("undef", Some(name)) if active => {
+                defines.remove(name);
+            }
+            ("define" | "undef", Some(_)) => {}
+            ("ifdef" | "ifndef", Some(name)) => branches.push(Branch {
+                line: i,
+                active: defines.contains_key(name) == (keyword == "ifdef"),
+                has_else: false,
+            }),
+            ("else", None) => match branches.last_mut() {
+                Some(branch) if !branch.has_else => {
+                    branch.active = !branch.active;
+                    branch.has_else = true;
+                }
+                _ => return Err(error("`#else` without `#ifdef`")),
+            },
+            ("endif", None) => {
+                if branches.pop().is_none() {
+                    return Err(error("`#endif` without `#ifdef`"));
+                }
+            }
+            _ => return Err(error(&format!("invalid directive `#{directive}`"))),
+        }
+        out.push('\n');
+    }
+
+    match branches.last() {
+        Some(branch) => Err(format!(
+            "{}:{}: `#ifdef` without `#endif`",
+            path.display(),
+            branch.line + 1
+        )),
+        None => Ok((out, LineMap(lines))),
+    }
+}
 
-        valid::Validator::new(valid::ValidationFlags::all(), valid::Capabilities::empty())
-            .validate(&module)
-            .map_err(|e| e.emit_to_string_with_path(&source, path.to_str().unwrap()))?;
+// replaces whole identifiers outside of comments, numbers like `1e5` are kept as is.
+// `comment` is the nesting of block comments at the start of the line, and at its end after.
+// The substitutions are returned as (column in the output, length of the value and of the name).
+fn substitute(
+    line: &str,
+    defines: &HashMap<String, String>,
+    comment: &mut usize,
+) -> (String, Vec<(usize, usize, usize)>) {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut out = String::with_capacity(line.len());
+    let mut substitutions = Vec::new();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            _ if rest.starts_with("/*") => {
+                *comment += 1;
+                2
+            }
+            _ if *comment > 0 && rest.starts_with("*/") => {
+                *comment -= 1;
+                2
+            }
+            _ if *comment > 0 => c.len_utf8(),
+            _ if rest.starts_with("//") => rest.len(),
+            _ if is_ident(c) => {
+                let len = rest.find(|c| !is_ident(c)).unwrap_or(rest.len());
+                let name = &rest[..len];
+                let value = replacement(name, defines);
+                if value != name {
+                    substitutions.push((out.len(), value.len(), len));
+                }
+                out += value;
+                rest = &rest[len..];
+                continue;
+            }
+            _ => c.len_utf8(),
+        };
+        out += &rest[..len];
+        rest = &rest[len..];
+    }
+    (out, substitutions)
+}
+
+fn replacement<'a>(word: &'a str, defines: &'a HashMap<String, String>) -> &'a str {
+    match defines.get(word) {
+        Some(value) if !word.starts_with(|c: char| c.is_ascii_digit()) => value,
+        _ => word,
+    }
+}
+
+// where a line of the preprocessed file starts, where it starts in the file
+// and the substitutions made in it, see `substitute`
+struct Line {
+    start: usize,
+    file_start: usize,
+    substitutions: Vec<(usize, usize, usize)>,
+}
+
+/// Maps offsets of a preprocessed file back into the file, line by line.
+struct LineMap(Vec<Line>);
+
+impl LineMap {
+    // with `end` set, an offset right after a substituted value maps to the end of its name
+    fn file_offset(&self, offset: usize, end: bool) -> usize {
+        let i = self.0.partition_point(|l| l.start <= offset);
+        let Some(line) = i.checked_sub(1).map(|i| &self.0[i]) else {
+            return offset;
+        };
+        let column = offset - line.start;
+        // difference of the columns in the file and in the output so far
+        let mut shift = 0;
+        for &(at, len, name_len) in &line.substitutions {
+            let (before, inside) = if end {
+                (column <= at, column <= at + len)
+            } else {
+                (column < at, column < at + len)
+            };
+            if before {
+                break;
+            }
+            if inside {
+                let name_end = if end { name_len } else { 0 };
+                return (line.file_start + at + name_end).saturating_add_signed(shift);
+            }
+            shift += name_len as isize - len as isize;
+        }
+        (line.file_start + column).saturating_add_signed(shift)
+    }
+}
 
-        let entries = module.entry_points;
+/// The module is assembled from the user's file and generated code, diagnostics are
+/// reported against the part their spans are in, so line numbers match the real files.
+/// Generated parts show up under names like `<bindings>`.
+struct SourceMap {
+    source: String,
+    files: SimpleFiles<String, String>,
+    // start of each part in `source`, its file id and how to map it back for preprocessed files
+    parts: Vec<(usize, usize, Option<LineMap>)>,
+}
 
-        if !entries.iter().any(|ep| ep.name.contains(VS_ENTRY)) {
-            source += &generate_vertex_main();
+impl SourceMap {
+    fn new() -> Self {
+        Self {
+            source: String::new(),
+            files: SimpleFiles::new(),
+            parts: Vec::new(),
         }
+    }
+
+    fn push(&mut self, name: impl Into<String>, text: String) {
+        self.parts.push((
+            self.source.len(),
+            self.files.add(name.into(), text.clone()),
+            None,
+        ));
+        self.source += &text;
+    }
+
+    // `file` is shown in diagnostics, its preprocessed text is what's parsed
+    fn push_preprocessed(
+        &mut self,
+        name: impl Into<String>,
+        file: String,
+        text: String,
+        map: LineMap,
+    ) {
+        self.parts.push((
+            self.source.len(),
+            self.files.add(name.into(), file),
+            Some(map),
+        ));
+        self.source += &text;
+    }
 
-        if !entries.iter().any(|ep| ep.name.contains(FS_ENTRY)) {
+    // the file of the span and the range in it, spans crossing parts are cut
+    fn locate(&self, span: naga::Span) -> Option<(usize, Range<usize>)> {
+        let range = span.to_range()?;
+        let (start, id, map) = self
+            .parts
+            .iter()
+            .rev()
+            .find(|(start, _, _)| *start <= range.start)?;
+        let len = self.files.get(*id).ok()?.source().len();
+        let range = range.start - start..range.end - start;
+        let range = match map {
+            Some(map) => map.file_offset(range.start, false)..map.file_offset(range.end, true),
+            None => range,
+        };
+        Some((*id, range.start.min(len)..range.end.min(len)))
+    }
+
+    fn parse(&self) -> Result<(naga::Module, valid::ModuleInfo), String> {
+        let module = wgsl::parse_str(&self.source)
+            .map_err(|e| self.emit(e.message(), e.labels(), Vec::new()))?;
+        let info =
+            valid::Validator::new(valid::ValidationFlags::all(), valid::Capabilities::empty())
+                .validate(&module)
+                .map_err(|e| {
+                    // like naga's own diagnostics, the causes are notes
+                    let labels = e.spans().map(|(span, msg)| (*span, msg.as_str()));
+                    self.emit(&e.as_inner().to_string(), labels, causes(e.as_inner()))
+                })?;
+        Ok((module, info))
+    }
+
+    fn emit<'a>(
+        &self,
+        message: &str,
+        labels: impl Iterator<Item = (naga::Span, &'a str)>,
+        notes: Vec<String>,
+    ) -> String {
+        let labels = labels
+            .filter_map(|(span, msg)| {
+                let (id, range) = self.locate(span)?;
+                Some(Label::primary(id, range).with_message(msg))
+            })
+            .collect();
+        let diagnostic = Diagnostic::error()
+            .with_message(message)
+            .with_labels(labels)
+            .with_notes(notes);
+        let mut writer = term::termcolor::NoColor::new(Vec::new());
+        term::emit(
+            &mut writer,
+            &term::Config::default(),
+            &self.files,
+            &diagnostic,
+        )
+        .expect("cannot write error");
+        String::from_utf8_lossy(&writer.into_inner()).into_owned()
+    }
+}
+
+// the preprocessed user's code followed by the declarations of the bindings, validated.
+// `debug_print` and its buffer are only declared if the code mentions them.
+fn parse_wgsl(
+    path: &Path,
+    source: String,
+    defines: &[(String, String)],
+    bindings: &BindingDecls,
+) -> Result<(SourceMap, naga::Module, valid::ModuleInfo), String> {
+    // the aliases come first, so they can be defined differently
+    let defines = BINDING_ALIASES
+        .iter()
+        .map(|(alias, name)| (alias.to_string(), name.to_string()))
+        .chain(defines.iter().cloned())
+        .collect::<Vec<_>>();
+    let (text, lines) = preprocess(&source, path, &defines)?;
+    let debug = ["debug_print", "DebugOutput"]
+        .iter()
+        .any(|name| text.contains(name));
+    let mut map = SourceMap::new();
+    map.push_preprocessed(path.display().to_string(), source, text, lines);
+    map.push("<bindings>", bindings.as_wgsl_string(debug));
+    if debug {
+        map.push("<debug_print>", generate_debug_print());
+    }
+    let (module, info) = map.parse()?;
+    Ok((map, module, info))
+}
+
+fn declares_debug_output(module: &naga::Module) -> bool {
+    module
+        .global_variables
+        .iter()
+        .any(|(_, var)| var.name.as_deref() == Some("DebugOutput"))
+}
+
+// the entry of the stage named exactly `name`, or the first one of the stage
+fn find_entry<'a>(
+    module: &'a naga::Module,
+    stage: naga::ShaderStage,
+    name: Option<&str>,
+) -> Option<&'a naga::EntryPoint> {
+    module
+        .entry_points
+        .iter()
+        .filter(|ep| ep.stage == stage)
+        .find(|ep| name.is_none_or(|name| ep.name == name))
+}
+
+fn entry_names(module: &naga::Module, stage: naga::ShaderStage) -> Vec<String> {
+    module
+        .entry_points
+        .iter()
+        .filter(|ep| ep.stage == stage)
+        .map(|ep| ep.name.clone())
+        .collect()
+}
+
+fn missing_entry(
+    path: &Path,
+    module: &naga::Module,
+    stage: naga::ShaderStage,
+    name: Option<&str>,
+) -> String {
+    let names = entry_names(module, stage);
+    let stage = format!("{stage:?}").to_lowercase();
+    match name {
+        Some(name) if names.is_empty() => format!("{path:?}: {stage} entry `{name}` not found"),
+        Some(name) => format!(
+            "{path:?}: {stage} entry `{name}` not found, the shader has `{}`",
+            names.join("`, `")
+        ),
+        None => format!("{path:?}: no {stage} entry point"),
+    }
+}
+
+// number of color attachments written by the fragment entry, `format` has room for some of them
+fn fragment_outputs(
+    module: &naga::Module,
+    entry: &naga::EntryPoint,
+    path: &Path,
+    format: TargetFormat,
+) -> Result<u32, String> {
+    let outputs = count_outputs(module, &entry.function)
+        .map_err(|e| format!("{path:?}: `{}` {e}", entry.name))?;
+    let max = format.max_outputs();
+    if outputs > max {
+        return Err(format!(
+            "{path:?}: `{}` writes {outputs} outputs, at most {max} {:?} attachments fit in a render pass",
+            entry.name,
+            format.texture_format()
+        ));
+    }
+    Ok(outputs)
+}
+
+fn causes(e: &dyn std::error::Error) -> Vec<String> {
+    let mut causes = Vec::new();
+    let mut cause = e.source();
+    while let Some(next) = cause {
+        causes.push(next.to_string());
+        cause = next.source();
+    }
+    causes
+}
+
+// a global is bound by its own name or, for blocks like GLSL's `uniform U { float Time; };`,
+// by the name of the block's only member
+fn bind_globals(module: &mut naga::Module, bindings: &BindingDecls) -> Result<(), String> {
+    for (_, var) in module.global_variables.iter_mut() {
+        let Some(ref mut binding) = var.binding else {
+            continue;
+        };
+        let member = match module.types[var.ty].inner {
+            naga::TypeInner::Struct { ref members, .. } if members.len() == 1 => {
+                members[0].name.as_deref()
+            }
+            _ => None,
+        };
+        let index = [var.name.as_deref(), member]
+            .into_iter()
+            .flatten()
+            .find_map(|name| bindings.index_of(name));
+        let Some(index) = index else {
             return Err(format!(
-                "{path:?} parsing error: `{FS_ENTRY}` entrie not found in source"
+                "`{}` (set {}, binding {}) doesn't match any of the global bindings",
+                var.name.as_deref().or(member).unwrap_or("<unnamed>"),
+                binding.group,
+                binding.binding
             ));
-        }
+        };
+        *binding = naga::ResourceBinding {
+            group: 0,
+            binding: index,
+        };
+    }
+    Ok(())
+}
+
+// `@location(n)` outputs, which can be fields of the returned struct
+// every output gets the attachment of its location, so they have to be 0, 1, 2...
+fn count_outputs(module: &naga::Module, function: &naga::Function) -> Result<u32, String> {
+    let location = |binding: &Option<naga::Binding>| match binding {
+        Some(naga::Binding::Location { location, .. }) => Some(*location),
+        _ => None,
+    };
+    let Some(ref result) = function.result else {
+        return Ok(1);
+    };
+    let members = match module.types[result.ty].inner {
+        naga::TypeInner::Struct { ref members, .. } => members.iter().map(|m| &m.binding).collect(),
+        _ => vec![&result.binding],
+    };
 
-        Ok(Self(source))
+    let mut locations = members.into_iter().filter_map(location).collect::<Vec<_>>();
+    locations.sort_unstable();
+    if let Some(gap) = (0..).zip(&locations).find(|(i, l)| i != *l) {
+        return Err(format!(
+            "writes no @location({}), outputs have to be at contiguous locations from 0",
+            gap.0
+        ));
     }
+    Ok((locations.len() as u32).max(1))
+}
 
-    pub fn as_str(&self) -> &str {
-        &self.0
+fn vertex_main(mesh: bool) -> String {
+    if mesh {
+        generate_mesh_vertex_main()
+    } else {
+        generate_vertex_main()
     }
 }
 
-fn generate_vertex_main() -> String {
+pub fn generate_vertex_main() -> String {
     format!(
 r#"
 @vertex
@@ -66,3 +692,304 @@ fn {VS_ENTRY}(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) ve
 }}"#
     )
 }
+
+// fragment shaders can take `@location(0) world_position`, `@location(1) normal` and `@location(2) uv`
+fn generate_mesh_vertex_main() -> String {
+    format!(
+        r#"
+struct MeshVertexOutput {{
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+}}
+
+@vertex
+fn {VS_ENTRY}(
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+) -> MeshVertexOutput {{
+    var out: MeshVertexOutput;
+    let world = ModelMatrix * vec4<f32>(position, 1.0);
+    out.clip_position = CameraProjection * CameraView * world;
+    out.world_position = world.xyz;
+    // the model matrix only scales uniformly
+    out.normal = normalize((ModelMatrix * vec4<f32>(normal, 0.0)).xyz);
+    out.uv = uv;
+    return out;
+}}"#
+    )
+}
+
+// `debug_print*(frag_coord.xy, id, value)` records values of the pixel selected on the host side
+fn generate_debug_print() -> String {
+    format!(
+        r#"
+struct DebugEntry {{
+    id: u32,
+    len: u32,
+    value: vec4<f32>,
+}}
+
+struct DebugBuffer {{
+    pixel: vec2<u32>,
+    enabled: u32,
+    count: atomic<u32>,
+    entries: array<DebugEntry, {DEBUG_ENTRIES}>,
+}}
+
+fn debug_print_n(frag_coord: vec2<f32>, id: u32, value: vec4<f32>, len: u32) {{
+    if DebugOutput.enabled == 0u || any(vec2<u32>(frag_coord) != DebugOutput.pixel) {{
+        return;
+    }}
+    let i = atomicAdd(&DebugOutput.count, 1u);
+    if i < {DEBUG_ENTRIES}u {{
+        DebugOutput.entries[i] = DebugEntry(id, len, value);
+    }}
+}}
+
+fn debug_print(frag_coord: vec2<f32>, id: u32, value: f32) {{
+    debug_print_n(frag_coord, id, vec4<f32>(value, 0.0, 0.0, 0.0), 1u);
+}}
+
+fn debug_print2(frag_coord: vec2<f32>, id: u32, value: vec2<f32>) {{
+    debug_print_n(frag_coord, id, vec4<f32>(value, 0.0, 0.0), 2u);
+}}
+
+fn debug_print3(frag_coord: vec2<f32>, id: u32, value: vec3<f32>) {{
+    debug_print_n(frag_coord, id, vec4<f32>(value, 0.0), 3u);
+}}
+
+fn debug_print4(frag_coord: vec2<f32>, id: u32, value: vec4<f32>) {{
+    debug_print_n(frag_coord, id, value, 4u);
+}}
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source: &str, defines: &[(&str, &str)]) -> Result<String, String> {
+        let defines = defines
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        preprocess(source, Path::new("test.wgsl"), &defines).map(|(text, _)| text)
+    }
+
+    // the non-empty lines of the output with their line numbers
+    fn lines(out: &str) -> Vec<(usize, &str)> {
+        out.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn nested_branches() {
+        let source = "\
+#ifdef A
+a
+#ifndef B
+not b
+#else
+b
+#endif
+#else
+not a
+#ifdef B
+b
+#endif
+#endif
+end";
+
+        assert_eq!(
+            lines(&run(source, &[]).unwrap()),
+            [(9, "not a"), (14, "end")]
+        );
+        assert_eq!(
+            lines(&run(source, &[("A", "1")]).unwrap()),
+            [(2, "a"), (4, "not b"), (14, "end")]
+        );
+        assert_eq!(
+            lines(&run(source, &[("A", "1"), ("B", "1")]).unwrap()),
+            [(2, "a"), (6, "b"), (14, "end")]
+        );
+        assert_eq!(
+            lines(&run(source, &[("B", "1")]).unwrap()),
+            [(9, "not a"), (11, "b"), (14, "end")]
+        );
+    }
+
+    #[test]
+    fn define_and_undef() {
+        let source = "\
+#define N 4
+#define M N * 2 // expanded here
+let a = M;
+#undef N
+#ifdef N
+let b = N;
+#endif
+let c = N;
+#define SKIPPED
+#ifdef SKIPPED
+#undef N
+#endif";
+
+        assert_eq!(
+            lines(&run(source, &[("N", "1")]).unwrap()),
+            [(3, "let a = 4 * 2;"), (8, "let c = N;")]
+        );
+    }
+
+    #[test]
+    fn defines_from_options_are_overridden() {
+        let source = "#define Q 3\nlet q = Q;";
+
+        assert_eq!(
+            lines(&run(source, &[("Q", "1")]).unwrap()),
+            [(2, "let q = 3;")]
+        );
+        assert_eq!(
+            lines(&run("let q = Q;", &[("Q", "1"), ("Q", "2")]).unwrap()),
+            [(1, "let q = 2;")]
+        );
+    }
+
+    #[test]
+    fn unbalanced_directives() {
+        let error = |source| run(source, &[]).unwrap_err();
+
+        assert_eq!(
+            error("#ifdef A\n\n"),
+            "test.wgsl:1: `#ifdef` without `#endif`"
+        );
+        assert_eq!(
+            error("#ifdef A\n#endif\n#ifndef B\n#ifdef C\n#endif"),
+            "test.wgsl:3: `#ifdef` without `#endif`"
+        );
+        assert_eq!(error("#else"), "test.wgsl:1: `#else` without `#ifdef`");
+        assert_eq!(
+            error("#ifdef A\n#else\n#else\n#endif"),
+            "test.wgsl:3: `#else` without `#ifdef`"
+        );
+        assert_eq!(error("\n#endif"), "test.wgsl:2: `#endif` without `#ifdef`");
+        assert_eq!(
+            error("#include \"a.wgsl\""),
+            "test.wgsl:1: invalid directive `#include \"a.wgsl\"`"
+        );
+    }
+
+    #[test]
+    fn lines_are_preserved() {
+        let source = "a\n#define X 1\n#ifdef Y\nb\n#endif\n\nc X\r\nd";
+        let out = run(source, &[]).unwrap();
+
+        assert_eq!(out.lines().count(), source.lines().count());
+        assert_eq!(lines(&out), [(1, "a"), (7, "c 1"), (8, "d")]);
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        let source = "\
+let a = X; // X stays
+/* X stays /* nested X */
+#define X 3
+X stays */ let b = X;
+let c = X /* X */ + X;";
+
+        assert_eq!(
+            lines(&run(source, &[("X", "2")]).unwrap()),
+            [
+                (1, "let a = 2; // X stays"),
+                (2, "/* X stays /* nested X */"),
+                (3, "#define X 3"),
+                (4, "X stays */ let b = 2;"),
+                (5, "let c = 2 /* X */ + 2;"),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_point_into_the_file() {
+        // the values are longer and shorter than the names in front of the error
+        let source = "\
+#define LONG_NAME 1.0
+#define S (1.0 + 2.0 * 3.0)
+
+fn f() -> f32 {
+    let a = S + LONG_NAME;
+    return S + LONG_NAME + missing;
+}
+";
+        let bindings = BindingDecls::new([]);
+        let Err(err) = parse_wgsl(Path::new("test.wgsl"), source.into(), &[], &bindings) else {
+            panic!("`missing` is not declared");
+        };
+
+        assert!(err.contains("test.wgsl:6:28"), "{err}");
+        assert!(
+            err.contains("6 │     return S + LONG_NAME + missing;"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn old_camera_names_are_aliases() {
+        let source = "@fragment fn fs_main() -> @location(0) vec4<f32> { return ProjectionMatrix * ViewMatrix * vec4<f32>(1.0); }";
+        let bindings = BindingDecls::new([]);
+        let (map, ..) = parse_wgsl(Path::new("test.wgsl"), source.into(), &[], &bindings).unwrap();
+
+        assert!(map.source.contains("CameraProjection * CameraView"));
+        assert_eq!(
+            bindings.index_of("ViewMatrix"),
+            bindings.index_of("CameraView")
+        );
+    }
+
+    #[test]
+    fn debug_output_is_only_declared_when_used() {
+        let debug = |body: &str| {
+            let source = format!(
+                "@fragment fn fs_main(@builtin(position) p: vec4<f32>) -> @location(0) vec4<f32> {{ {body} return vec4<f32>(1.0); }}"
+            );
+            let bindings = BindingDecls::new([]);
+            let (_, module, _) =
+                parse_wgsl(Path::new("test.wgsl"), source, &[], &bindings).unwrap();
+            declares_debug_output(&module)
+        };
+
+        assert!(!debug(""));
+        assert!(debug("debug_print(p.xy, 1u, 2.0);"));
+    }
+
+    #[test]
+    fn outputs_are_counted() {
+        let outputs = |locations: &[u32], format| {
+            let members = locations
+                .iter()
+                .map(|l| format!("@location({l}) c{l}: vec4<f32>,"))
+                .collect::<String>();
+            let source = format!(
+                "struct Out {{ {members} }}\n@fragment fn fs_main() -> Out {{ var out: Out; return out; }}"
+            );
+            let path = Path::new("test.wgsl");
+            let bindings = BindingDecls::new([]);
+            let (_, module, _) = parse_wgsl(path, source, &[], &bindings).unwrap();
+            let fs = find_entry(&module, naga::ShaderStage::Fragment, Some(FS_ENTRY)).unwrap();
+            fragment_outputs(&module, fs, path, format)
+        };
+
+        assert_eq!(outputs(&[1, 0, 2], TargetFormat::Rgba8), Ok(3));
+        assert_eq!(outputs(&[0, 1, 2, 3], TargetFormat::Rgba16Float), Ok(4));
+        let gap = outputs(&[0, 2], TargetFormat::Rgba8).unwrap_err();
+        assert!(gap.contains("writes no @location(1)"), "{gap}");
+        let many = outputs(&[0, 1, 2], TargetFormat::Rgba32Float).unwrap_err();
+        assert!(many.contains("at most 2"), "{many}");
+    }
+}
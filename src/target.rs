@@ -0,0 +1,533 @@
+use half::f16;
+use winit::dpi::PhysicalSize;
+
+// the default `maxColorAttachmentBytesPerSample` and `maxColorAttachments` of WebGPU
+const MAX_COLOR_ATTACHMENT_BYTES_PER_SAMPLE: u32 = 32;
+const MAX_COLOR_ATTACHMENTS: u32 = 8;
+
+/// Format of the offscreen texture the user's shader renders into.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum TargetFormat {
+    #[default]
+    Rgba8,
+    Rgba16Float,
+    Rgba32Float,
+}
+
+impl TargetFormat {
+    pub fn texture_format(&self) -> wgpu::TextureFormat {
+        match self {
+            Self::Rgba8 => wgpu::TextureFormat::Rgba8UnormSrgb,
+            Self::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+            Self::Rgba32Float => wgpu::TextureFormat::Rgba32Float,
+        }
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        *self != Self::Rgba8
+    }
+
+    /// Color attachments of this format a render pass can have, WebGPU allows
+    /// `maxColorAttachmentBytesPerSample` (32 by default), which wgpu doesn't check yet.
+    pub fn max_outputs(&self) -> u32 {
+        // render target pixel byte costs of the WebGPU spec
+        let cost = match self {
+            Self::Rgba8 | Self::Rgba16Float => 8,
+            Self::Rgba32Float => 16,
+        };
+        (MAX_COLOR_ATTACHMENT_BYTES_PER_SAMPLE / cost).min(MAX_COLOR_ATTACHMENTS)
+    }
+
+    /// Channel values of texels read back from a target of this format,
+    /// 8 bit targets are decoded back into the linear values written by the shader.
+    pub fn decode(&self, data: &[u8]) -> Vec<f32> {
+        match self {
+            Self::Rgba8 => data
+                .chunks_exact(4)
+                .flat_map(|px| {
+                    let [r, g, b] = [px[0], px[1], px[2]].map(|c| srgb_to_linear(c as f32 / 255.0));
+                    // alpha is stored linearly
+                    [r, g, b, px[3] as f32 / 255.0]
+                })
+                .collect(),
+            Self::Rgba16Float => data
+                .chunks_exact(2)
+                .map(|b| f16::from_le_bytes([b[0], b[1]]).to_f32())
+                .collect(),
+            Self::Rgba32Float => data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        }
+    }
+}
+
+impl std::str::FromStr for TargetFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rgba8" => Ok(Self::Rgba8),
+            "rgba16f" => Ok(Self::Rgba16Float),
+            "rgba32f" => Ok(Self::Rgba32Float),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported target format `{s}`, expected rgba8, rgba16f or rgba32f"
+            )),
+        }
+    }
+}
+
+/// Counts of pixels with invalid values, a pixel is counted once by its first issue.
+#[derive(Clone, Copy, Default)]
+pub struct PixelStats {
+    pub nan: usize,
+    pub infinite: usize,
+    pub negative: usize,
+    pub above_one: usize,
+}
+
+impl PixelStats {
+    /// `values` are rgba pixels decoded with [`TargetFormat::decode`].
+    pub fn count(values: &[f32]) -> Self {
+        let mut stats = Self::default();
+        for px in values.chunks_exact(4) {
+            if px.iter().any(|c| c.is_nan()) {
+                stats.nan += 1;
+            } else if px.iter().any(|c| c.is_infinite()) {
+                stats.infinite += 1;
+            } else if px[..3].iter().any(|c| *c < 0.0) {
+                stats.negative += 1;
+            } else if px[..3].iter().any(|c| *c > 1.0) {
+                stats.above_one += 1;
+            }
+        }
+
+        stats
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nan + self.infinite + self.negative + self.above_one == 0
+    }
+}
+
+impl std::ops::AddAssign for PixelStats {
+    fn add_assign(&mut self, other: Self) {
+        self.nan += other.nan;
+        self.infinite += other.infinite;
+        self.negative += other.negative;
+        self.above_one += other.above_one;
+    }
+}
+
+impl std::fmt::Display for PixelStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} NaN, {} infinite, {} negative, {} above 1.0",
+            self.nan, self.infinite, self.negative, self.above_one
+        )
+    }
+}
+
+/// Operator applied to the render target on its way to the window (and to captured frames).
+#[derive(Clone, Copy, Default)]
+pub enum ToneMapping {
+    /// Values are just clamped.
+    #[default]
+    None,
+    Reinhard,
+    Aces,
+}
+
+impl ToneMapping {
+    fn as_wgsl_str(&self) -> &'static str {
+        match self {
+            Self::None => "c",
+            Self::Reinhard => "c / (1.0 + c)",
+            // Krzysztof Narkowicz's fit of the ACES filmic curve
+            Self::Aces => "(c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)",
+        }
+    }
+}
+
+impl std::str::FromStr for ToneMapping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "reinhard" => Ok(Self::Reinhard),
+            "aces" => Ok(Self::Aces),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported tone mapping `{s}`, expected none, reinhard or aces"
+            )),
+        }
+    }
+}
+
+/// How the values written by the user's shader are interpreted.
+#[derive(Clone, Copy, Default)]
+pub enum ColorSpace {
+    /// Linear values, encoded to sRGB for the window and captures.
+    #[default]
+    Linear,
+    /// Already sRGB encoded values that are displayed as is, like in ShaderToy.
+    Srgb,
+}
+
+impl std::str::FromStr for ColorSpace {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "srgb" => Ok(Self::Srgb),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported color space `{s}`, expected linear or srgb"
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct TargetOptions {
+    pub format: TargetFormat,
+    pub tone_mapping: ToneMapping,
+    /// In stops, applied before tone mapping.
+    pub exposure: f32,
+    pub output_space: ColorSpace,
+}
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Offscreen textures that hold the outputs of the user's shader, with their depth buffer.
+pub struct RenderTarget {
+    /// One texture per `@location(n)` output of the fragment shader.
+    pub attachments: Vec<(wgpu::Texture, wgpu::TextureView)>,
+    pub depth: wgpu::TextureView,
+    pub format: TargetFormat,
+}
+
+impl RenderTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        size: &PhysicalSize<u32>,
+        format: TargetFormat,
+        count: u32,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        };
+        let attachments = (0..count.max(1))
+            .map(|_| {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("render target"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: format.texture_format(),
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC,
+                    view_formats: &[],
+                });
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                (texture, view)
+            })
+            .collect();
+        let depth = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("depth"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: DEPTH_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            attachments,
+            depth,
+            format,
+        }
+    }
+}
+
+/// Draws a [`RenderTarget`] into the window or a capture texture,
+/// applying exposure, tone mapping and the conversion into the destination's color space.
+pub struct Presenter {
+    layout: wgpu::BindGroupLayout,
+    // one pipeline per destination format, with and without diagnostics
+    pipelines: Vec<((wgpu::TextureFormat, bool), wgpu::RenderPipeline)>,
+}
+
+impl Presenter {
+    pub fn new(
+        device: &wgpu::Device,
+        options: &TargetOptions,
+        formats: &[wgpu::TextureFormat],
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    // loaded without a sampler, so 32 bit float targets are fine as well
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipelines = formats
+            .iter()
+            .flat_map(|format| [(*format, false), (*format, true)])
+            .map(|(format, diagnostics)| {
+                let src = present_shader(options, format, diagnostics);
+                let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: Some("present"),
+                    source: wgpu::ShaderSource::Wgsl(src.into()),
+                });
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("present"),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &module,
+                        entry_point: crate::ctx::VS_ENTRY,
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &module,
+                        entry_point: crate::ctx::FS_ENTRY,
+                        targets: &[Some(format.into())],
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                });
+                ((format, diagnostics), pipeline)
+            })
+            .collect();
+
+        Self { layout, pipelines }
+    }
+
+    /// With `diagnostics` invalid values are highlighted instead, see [`present_shader`].
+    pub fn draw(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        destination: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+        diagnostics: bool,
+    ) {
+        let key = (format, diagnostics);
+        let Some((_, pipeline)) = self.pipelines.iter().find(|(k, _)| *k == key) else {
+            return log::error!("No presentation pipeline for {format:?}");
+        };
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(source),
+            }],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("present"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: destination,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.set_pipeline(pipeline);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// The target holds values in the working (linear) space,
+// unless `ColorSpace::Srgb` says that the shader writes display values itself.
+// Destinations with an sRGB format encode on write, the others have to be encoded here.
+// Diagnostics show NaN pixels in magenta, infinite in cyan, negative in blue,
+// above 1.0 in red and the rest as dimmed luminance.
+fn present_shader(
+    options: &TargetOptions,
+    destination: wgpu::TextureFormat,
+    diagnostics: bool,
+) -> String {
+    let output_space = if diagnostics {
+        ColorSpace::Linear
+    } else {
+        options.output_space
+    };
+    let conversion = match (output_space, destination.is_srgb()) {
+        (ColorSpace::Linear, true) | (ColorSpace::Srgb, false) => "c",
+        (ColorSpace::Linear, false) => "linear_to_srgb(c)",
+        (ColorSpace::Srgb, true) => "srgb_to_linear(c)",
+    };
+    let color = if diagnostics {
+        format!(
+            "
+    var c = vec3<f32>(dot(clamp(texel.rgb, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(0.2126, 0.7152, 0.0722)) * 0.3);
+    // NaN and infinity are checked by bits, comparisons with NaN can be optimized out
+    let bits = bitcast<vec4<u32>>(texel) & vec4<u32>(0x7fffffffu);
+    if any(bits > vec4<u32>(0x7f800000u)) {{
+        c = vec3<f32>(1.0, 0.0, 1.0);
+    }} else if any(bits == vec4<u32>(0x7f800000u)) {{
+        c = vec3<f32>(0.0, 1.0, 1.0);
+    }} else if any(texel.rgb < vec3<f32>(0.0)) {{
+        c = vec3<f32>(0.0, 0.0, 1.0);
+    }} else if any(texel.rgb > vec3<f32>(1.0)) {{
+        c = vec3<f32>(1.0, 0.0, 0.0);
+    }}
+    return vec4<f32>({conversion}, 1.0);",
+        )
+    } else {
+        format!(
+            "
+    var c = max(texel.rgb * {exposure}, vec3<f32>(0.0));
+    c = clamp({tone_mapping}, vec3<f32>(0.0), vec3<f32>(1.0));
+    return vec4<f32>({conversion}, texel.a);",
+            exposure = format_args!("{:?}", 2f32.powf(options.exposure)),
+            tone_mapping = options.tone_mapping.as_wgsl_str(),
+        )
+    };
+
+    format!(
+        r#"
+@group(0) @binding(0) var Frame: texture_2d<f32>;
+
+{vertex_main}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {{
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {{
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}}
+
+@fragment
+fn {fs_entry}(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {{
+    let texel = textureLoad(Frame, vec2<i32>(frag_coord.xy), 0);{color}
+}}
+"#,
+        vertex_main = crate::pp::generate_vertex_main(),
+        fs_entry = crate::ctx::FS_ENTRY,
+    )
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Swaps the channels of BGRA texels in place, other formats are left as they are.
+pub fn swizzle_to_rgba(format: wgpu::TextureFormat, frame: &mut [u8]) {
+    if let wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb = format {
+        frame.chunks_exact_mut(4).for_each(|px| px.swap(0, 2));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linear_to_srgb(c: f32) -> f32 {
+        if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    }
+
+    // linear values of an 8 texel wide gradient, sampled at texel centers
+    fn gradient() -> Vec<f32> {
+        (0..8).map(|i| (i as f32 + 0.5) / 8.0).collect()
+    }
+
+    #[test]
+    fn decodes_srgb_gradient_into_linear_values() {
+        let data = gradient()
+            .iter()
+            .flat_map(|&c| {
+                let encoded = (linear_to_srgb(c) * 255.0).round() as u8;
+                [encoded, encoded, encoded, (c * 255.0).round() as u8]
+            })
+            .collect::<Vec<_>>();
+        let decoded = TargetFormat::Rgba8.decode(&data);
+
+        assert_eq!(decoded.len(), 8 * 4);
+        for (px, c) in decoded.chunks_exact(4).zip(gradient()) {
+            // one step of 8 bit quantization in sRGB is at most ~0.0125 in linear space
+            assert!(
+                px[..3].iter().all(|v| (v - c).abs() < 0.0125),
+                "{px:?} != {c}"
+            );
+            assert!((px[3] - c).abs() <= 0.5 / 255.0, "{px:?} != {c}");
+        }
+    }
+
+    #[test]
+    fn decodes_float_gradients_exactly() {
+        let values = gradient();
+        let f32_data = values
+            .iter()
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(TargetFormat::Rgba32Float.decode(&f32_data), values);
+
+        // multiples of 1/16 are exact in half precision
+        let f16_data = values
+            .iter()
+            .flat_map(|&c| f16::from_f32(c).to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(TargetFormat::Rgba16Float.decode(&f16_data), values);
+    }
+
+    #[test]
+    fn swizzles_bgra_frames() {
+        let bgra = [10, 20, 30, 255, 40, 50, 60, 128];
+
+        let mut frame = bgra;
+        swizzle_to_rgba(wgpu::TextureFormat::Bgra8UnormSrgb, &mut frame);
+        assert_eq!(frame, [30, 20, 10, 255, 60, 50, 40, 128]);
+
+        let mut frame = bgra;
+        swizzle_to_rgba(wgpu::TextureFormat::Rgba8UnormSrgb, &mut frame);
+        assert_eq!(frame, bgra);
+    }
+
+    #[test]
+    fn srgb_round_trip() {
+        for c in gradient() {
+            assert!((srgb_to_linear(linear_to_srgb(c)) - c).abs() < 1e-5);
+        }
+    }
+}
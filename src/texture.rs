@@ -0,0 +1,243 @@
+use anyhow::Context;
+use half::f16;
+use std::path::{Path, PathBuf};
+
+/// Decoded pixels ready to be uploaded with [`crate::bind::TextureBinding::update`].
+pub struct TextureData {
+    pub size: wgpu::Extent3d,
+    pub format: wgpu::TextureFormat,
+    pub data: Vec<u8>,
+}
+
+/// Loads a cubemap either from six comma separated face images
+/// (in the +X, -X, +Y, -Y, +Z, -Z order) or from a single equirectangular image.
+/// Faces larger than `max_dimension` (the device's `max_texture_dimension_2d`) are rejected,
+/// `float32` tells if the device can filter 32 bit float textures.
+pub fn load_cubemap(spec: &str, max_dimension: u32, float32: bool) -> anyhow::Result<TextureData> {
+    let faces = spec.split(',').collect::<Vec<_>>();
+    match faces.len() {
+        1 => equirect_to_cubemap(spec, &open_image(spec)?, max_dimension, float32),
+        6 => {
+            let faces = faces
+                .into_iter()
+                .map(|f| Ok(open_image(f)?.to_rgba8()))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let (width, height) = faces[0].dimensions();
+            if width != height || faces.iter().any(|f| f.dimensions() != (width, height)) {
+                anyhow::bail!("{spec}: cubemap faces must be squares of the same size");
+            }
+            check_size(spec, "cubemap face", &[width, height], max_dimension)?;
+
+            Ok(TextureData {
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 6,
+                },
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                data: faces.into_iter().flat_map(|f| f.into_raw()).collect(),
+            })
+        }
+        n => anyhow::bail!("{spec}: expected 1 equirectangular image or 6 faces, got {n}"),
+    }
+}
+
+/// Loads a volume either from a directory of equally sized slice images (sorted by name)
+/// or from a raw file of 8 or 16 bit unsigned or 32 bit float scalars with the size in its name,
+/// like `bonsai_256x256x256_uint8.raw`.
+/// Volumes larger than `max_dimension` (the device's `max_texture_dimension_3d`) are rejected.
+/// With `float32` (the device can filter 32 bit float textures) 16 and 32 bit scalars
+/// are kept as 32 bit floats, otherwise they're converted to 16 bit ones.
+pub fn load_volume(spec: &str, max_dimension: u32, float32: bool) -> anyhow::Result<TextureData> {
+    let path = Path::new(spec);
+    if path.is_dir() {
+        let tex = load_slices(path)?;
+        let size = tex.size;
+        check_size(
+            spec,
+            "volume",
+            &[size.width, size.height, size.depth_or_array_layers],
+            max_dimension,
+        )?;
+        return Ok(tex);
+    }
+
+    let name = path
+        .file_stem()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    let [width, height, depth] = name
+        .split(['_', '.', '-'])
+        .find_map(parse_dimensions)
+        .with_context(|| format!("{spec}: volume size not found in the file name"))?;
+    check_size(spec, "volume", &[width, height, depth], max_dimension)?;
+    let texels = [width, height, depth]
+        .iter()
+        .try_fold(1usize, |n, d| n.checked_mul(*d as usize))
+        .with_context(|| format!("{spec}: volume size overflows"))?;
+    let raw = std::fs::read(path).with_context(|| format!("Failed to read {spec}"))?;
+
+    let (format, data) = if raw.len() == texels {
+        (wgpu::TextureFormat::R8Unorm, raw)
+    } else if Some(raw.len()) == texels.checked_mul(2) {
+        // there is no filterable 16 bit normalized format without extra features
+        let values = raw
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as f32 / u16::MAX as f32);
+        (float_format(float32, 1), float_texels(values, float32))
+    } else if Some(raw.len()) == texels.checked_mul(4) {
+        let values = raw
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+        (float_format(float32, 1), float_texels(values, float32))
+    } else {
+        anyhow::bail!(
+            "{spec}: expected {texels} 8, 16 or 32 bit texels, got {len} bytes",
+            len = raw.len()
+        );
+    };
+
+    Ok(TextureData {
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: depth,
+        },
+        format,
+        data,
+    })
+}
+
+fn load_slices(dir: &Path) -> anyhow::Result<TextureData> {
+    let mut paths = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {dir:?}"))?
+        .filter_map(|e| Some(e.ok()?.path()))
+        .filter(|p| image::ImageFormat::from_path(p).is_ok())
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+
+    let slices = paths
+        .iter()
+        .map(|p| Ok(open_image(p)?.to_rgba8()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let Some((width, height)) = slices.first().map(|s| s.dimensions()) else {
+        anyhow::bail!("{dir:?}: no slice images found");
+    };
+    if slices.iter().any(|s| s.dimensions() != (width, height)) {
+        anyhow::bail!("{dir:?}: all slices must have the same size");
+    }
+
+    Ok(TextureData {
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: slices.len() as _,
+        },
+        // volumes usually hold data rather than colors
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        data: slices.into_iter().flat_map(|s| s.into_raw()).collect(),
+    })
+}
+
+/// Resamples an equirectangular panorama into six float faces, so HDR environments keep
+/// their range. They are `Rgba32Float` with `float32`, `Rgba16Float` otherwise.
+fn equirect_to_cubemap(
+    spec: &str,
+    image: &image::DynamicImage,
+    max_dimension: u32,
+    float32: bool,
+) -> anyhow::Result<TextureData> {
+    let face_size = (image.width() / 4).max(1);
+    check_size(spec, "cubemap face", &[face_size, face_size], max_dimension)?;
+    let image = image.to_rgba32f();
+    let (width, height) = image.dimensions();
+    let mut texels = Vec::with_capacity((face_size * face_size * 6 * 4) as usize);
+
+    for face in 0..6 {
+        for y in 0..face_size {
+            for x in 0..face_size {
+                let u = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+                let v = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+                let [dx, dy, dz] = face_direction(face, u, v);
+                let len = (dx * dx + dy * dy + dz * dz).sqrt();
+                let (dx, dy, dz) = (dx / len, dy / len, dz / len);
+
+                let s = 0.5 + dx.atan2(-dz) / std::f32::consts::TAU;
+                let t = dy.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+                let px = ((s * width as f32) as u32).min(width - 1);
+                let py = ((t * height as f32) as u32).min(height - 1);
+
+                texels.extend(image.get_pixel(px, py).0);
+            }
+        }
+    }
+
+    Ok(TextureData {
+        size: wgpu::Extent3d {
+            width: face_size,
+            height: face_size,
+            depth_or_array_layers: 6,
+        },
+        format: float_format(float32, 4),
+        data: float_texels(texels.into_iter(), float32),
+    })
+}
+
+// only devices that can filter 32 bit floats can sample them with the shared `Sampler`,
+// they're converted to 16 bit floats for the others
+fn float_format(float32: bool, channels: u32) -> wgpu::TextureFormat {
+    match (float32, channels) {
+        (true, 1) => wgpu::TextureFormat::R32Float,
+        (true, _) => wgpu::TextureFormat::Rgba32Float,
+        (false, 1) => wgpu::TextureFormat::R16Float,
+        (false, _) => wgpu::TextureFormat::Rgba16Float,
+    }
+}
+
+fn float_texels(values: impl Iterator<Item = f32>, float32: bool) -> Vec<u8> {
+    if float32 {
+        values.flat_map(f32::to_le_bytes).collect()
+    } else {
+        values
+            .flat_map(|v| f16::from_f32(v).to_le_bytes())
+            .collect()
+    }
+}
+
+// direction towards the texel at `u`, `v` (-1..1, right and down) of a cubemap layer
+fn face_direction(face: u32, u: f32, v: f32) -> [f32; 3] {
+    match face {
+        0 => [1.0, -v, -u],
+        1 => [-1.0, -v, u],
+        2 => [u, 1.0, v],
+        3 => [u, -1.0, -v],
+        4 => [u, -v, 1.0],
+        _ => [-u, -v, -1.0],
+    }
+}
+
+// creating a texture above the limit would be a validation error
+fn check_size(spec: &str, what: &str, size: &[u32], max_dimension: u32) -> anyhow::Result<()> {
+    if size.iter().any(|d| *d > max_dimension) {
+        let size = size
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join("x");
+        anyhow::bail!(
+            "{spec}: {what} size {size} exceeds the limit of {max_dimension} per dimension"
+        );
+    }
+    Ok(())
+}
+
+fn parse_dimensions(s: &str) -> Option<[u32; 3]> {
+    let mut dims = s.split('x').map(|d| d.parse::<u32>().ok());
+    let size = [dims.next()??, dims.next()??, dims.next()??];
+    (dims.next().is_none() && size.iter().all(|d| *d > 0)).then_some(size)
+}
+
+fn open_image(path: impl AsRef<Path>) -> anyhow::Result<image::DynamicImage> {
+    let path = path.as_ref();
+    image::open(path).with_context(|| format!("Failed to load {path:?}"))
+}
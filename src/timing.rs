@@ -0,0 +1,377 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Passes of a frame that are timed on the GPU, in the order they are encoded.
+pub const PASSES: [&str; 2] = ["shader", "present"];
+// frames shown by the HUD graph
+pub const HISTORY: usize = 128;
+
+/// GPU durations of [`PASSES`] measured with timestamp queries.
+/// Results are read back asynchronously, so only some frames are timed.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    read_buffer: wgpu::Buffer,
+    // nanoseconds per tick
+    period: f32,
+    // results were resolved into `read_buffer`, which is being mapped
+    pending: bool,
+    mapped: Arc<AtomicBool>,
+}
+
+impl GpuTimer {
+    const QUERIES: u32 = PASSES.len() as u32 + 1;
+    const SIZE: u64 = Self::QUERIES as u64 * std::mem::size_of::<u64>() as u64;
+
+    /// `None` if the device doesn't support timestamp queries.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let buffer = |usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("timestamps"),
+                size: Self::SIZE,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+
+        Some(Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: Self::QUERIES,
+            }),
+            resolve_buffer: buffer(
+                wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            ),
+            read_buffer: buffer(wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST),
+            period: queue.get_timestamp_period(),
+            pending: false,
+            mapped: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Whether new timestamps can be written, the previous ones have to be read first.
+    pub fn is_idle(&self) -> bool {
+        !self.pending
+    }
+
+    /// Index 0 goes before the first pass, `n` after the `n`-th one.
+    pub fn write(&self, encoder: &mut wgpu::CommandEncoder, idx: u32) {
+        encoder.write_timestamp(&self.query_set, idx);
+    }
+
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..Self::QUERIES, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.read_buffer, 0, Self::SIZE);
+    }
+
+    /// Has to be called after the encoder with [`GpuTimer::resolve`] is submitted.
+    pub fn map(&mut self) {
+        self.pending = true;
+        let mapped = self.mapped.clone();
+        self.read_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| match result {
+                Ok(_) => mapped.store(true, Ordering::Release),
+                Err(e) => log::error!("Failed to map timestamps: {e}"),
+            });
+    }
+
+    /// Milliseconds spent in each of [`PASSES`], if the results are available.
+    pub fn poll(&mut self, device: &wgpu::Device, wait: bool) -> Option<[f32; PASSES.len()]> {
+        if !self.pending {
+            return None;
+        }
+        device.poll(if wait {
+            wgpu::Maintain::Wait
+        } else {
+            wgpu::Maintain::Poll
+        });
+        if !self.mapped.swap(false, Ordering::Acquire) {
+            return None;
+        }
+
+        let slice = self.read_buffer.slice(..);
+        let ticks: [u64; Self::QUERIES as usize] =
+            bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        self.read_buffer.unmap();
+        self.pending = false;
+
+        let mut times = [0.0; PASSES.len()];
+        for (i, time) in times.iter_mut().enumerate() {
+            let ticks = ticks[i + 1].saturating_sub(ticks[i]);
+            *time = ticks as f32 * self.period / 1_000_000.0;
+        }
+
+        Some(times)
+    }
+}
+
+/// Minimum, average and maximum of some durations.
+#[derive(Clone, Copy, Default)]
+pub struct Summary {
+    pub min: f32,
+    pub avg: f32,
+    pub max: f32,
+}
+
+impl Summary {
+    pub fn of(values: impl IntoIterator<Item = f32>) -> Self {
+        let (mut min, mut max, mut sum, mut count) = (f32::MAX, f32::MIN, 0.0, 0);
+        for v in values {
+            min = min.min(v);
+            max = max.max(v);
+            sum += v;
+            count += 1;
+        }
+        if count == 0 {
+            return Self::default();
+        }
+
+        Self {
+            min,
+            avg: sum / count as f32,
+            max,
+        }
+    }
+
+    /// `{"min": .., "avg": .., "max": ..}` in milliseconds, rounded to microseconds.
+    pub fn to_json(self) -> serde_json::Value {
+        let round = |v: f32| (v as f64 * 1000.0).round() / 1000.0;
+        serde_json::json!({
+            "min": round(self.min),
+            "avg": round(self.avg),
+            "max": round(self.max),
+        })
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:.2}/{:.2}/{:.2} ms", self.min, self.avg, self.max)
+    }
+}
+
+/// Rolling window of frame times in milliseconds.
+#[derive(Default)]
+pub struct FrameStats {
+    frames: VecDeque<f32>,
+    // latest GPU times, there are fewer of them than frames
+    gpu: VecDeque<[f32; PASSES.len()]>,
+}
+
+impl FrameStats {
+    pub fn push_frame(&mut self, ms: f32) {
+        push_limited(&mut self.frames, ms);
+    }
+
+    pub fn push_gpu(&mut self, times: [f32; PASSES.len()]) {
+        push_limited(&mut self.gpu, times);
+    }
+
+    pub fn frames(&self) -> Summary {
+        Summary::of(self.frames.iter().copied())
+    }
+
+    pub fn pass(&self, idx: usize) -> Option<Summary> {
+        (!self.gpu.is_empty()).then(|| Summary::of(self.gpu.iter().map(|t| t[idx])))
+    }
+
+    /// One line summary, like `frame 16.20/16.67/17.01 ms | shader 1.20/1.31/1.52 ms | ...`.
+    pub fn summary(&self) -> String {
+        let mut line = format!("frame {}", self.frames());
+        for (i, pass) in PASSES.iter().enumerate() {
+            if let Some(s) = self.pass(i) {
+                line += &format!(" | {pass} {s}");
+            }
+        }
+
+        line
+    }
+}
+
+fn push_limited<T>(values: &mut VecDeque<T>, value: T) {
+    if values.len() == HISTORY {
+        values.pop_front();
+    }
+    values.push_back(value);
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct HudUniform {
+    // x, y, width, height of the graph in pixels
+    rect: [f32; 4],
+    // milliseconds at the top of the graph
+    scale: [f32; 4],
+    // frame time and the sum of GPU pass times, oldest first
+    samples: [[f32; 4]; HISTORY],
+}
+
+/// Graph of the recent frame times drawn over the window.
+pub struct Hud {
+    pipeline: wgpu::RenderPipeline,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Hud {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("hud"),
+            size: std::mem::size_of::<HudUniform>() as _,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("hud"),
+            source: wgpu::ShaderSource::Wgsl(hud_shader().into()),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("hud"),
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[&layout],
+                    push_constant_ranges: &[],
+                }),
+            ),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: crate::ctx::VS_ENTRY,
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: crate::ctx::FS_ENTRY,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            buffer,
+            bind_group,
+        }
+    }
+
+    /// Draws the graph in the bottom left corner of `view`.
+    pub fn draw(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        height: u32,
+        stats: &FrameStats,
+    ) {
+        let mut samples = [[0.0; 4]; HISTORY];
+        let offset = HISTORY - stats.frames.len();
+        for (i, ms) in stats.frames.iter().enumerate() {
+            samples[offset + i][0] = *ms;
+        }
+        let offset = HISTORY - stats.gpu.len();
+        for (i, times) in stats.gpu.iter().enumerate() {
+            samples[offset + i][1] = times.iter().sum();
+        }
+        let max = stats.frames.iter().copied().fold(0.0, f32::max);
+        let uniform = HudUniform {
+            rect: [8.0, height as f32 - 8.0 - 64.0, HISTORY as f32 * 2.0, 64.0],
+            // at least two 60 fps frames, so the 16.7 ms line is always visible
+            scale: [(max * 1.2).max(33.4), 0.0, 0.0, 0.0],
+            samples,
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("hud"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// frame times are green bars with the GPU part in orange, the white line is 60 fps
+fn hud_shader() -> String {
+    format!(
+        r#"
+struct Hud {{
+    rect: vec4<f32>,
+    scale: vec4<f32>,
+    samples: array<vec4<f32>, {HISTORY}>,
+}}
+
+@group(0) @binding(0) var<uniform> Graph: Hud;
+
+{vertex_main}
+
+@fragment
+fn {fs_entry}(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {{
+    let local = (frag_coord.xy - Graph.rect.xy) / Graph.rect.zw;
+    if any(local < vec2<f32>(0.0)) || any(local >= vec2<f32>(1.0)) {{
+        discard;
+    }}
+    let sample = Graph.samples[u32(local.x * {HISTORY}.0)];
+    let ms = (1.0 - local.y) * Graph.scale.x;
+    var color = vec4<f32>(0.0, 0.0, 0.0, 0.5);
+    if ms < sample.y {{
+        color = vec4<f32>(1.0, 0.5, 0.0, 0.9);
+    }} else if ms < sample.x {{
+        color = vec4<f32>(0.2, 0.8, 0.2, 0.9);
+    }}
+    if abs(ms - 16.667) < Graph.scale.x / Graph.rect.w {{
+        color = vec4<f32>(1.0, 1.0, 1.0, 0.8);
+    }}
+    return color;
+}}
+"#,
+        vertex_main = crate::pp::generate_vertex_main(),
+        fs_entry = crate::ctx::FS_ENTRY,
+    )
+}
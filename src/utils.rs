@@ -1,4 +1,8 @@
-use crate::ctx::FrameBuffer;
+use crate::{
+    capture::{HdrFormat, Soundtrack, VideoFormat},
+    ctx::FrameBuffer,
+    target::TargetFormat,
+};
 use anyhow::Context;
 use chrono::offset::Local;
 use crossterm::{
@@ -6,41 +10,169 @@ use crossterm::{
     terminal::{Clear, ClearType},
 };
 use notify::Watcher;
-use std::{fs::File, io::Write, sync::mpsc, time::Instant};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant},
+};
 use winit::dpi::PhysicalSize;
 
 pub type RawFrame = Vec<u8>;
 
-#[allow(dead_code)]
+// editors save with a few events in a row (write, rename, chmod, ...)
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Watches the directories of files, so a file can be replaced by a rename
+/// (atomic saves) or deleted and created again.
 pub struct FileWatcher {
-    pub receiver: mpsc::Receiver<notify::Result<notify::Event>>,
+    receiver: mpsc::Receiver<notify::Result<notify::Event>>,
     watcher: notify::RecommendedWatcher,
+    // `false` after the directory was removed or the watcher failed
+    dirs: Vec<(PathBuf, bool)>,
+    // in the canonical directory, so they match the paths of events
+    files: Vec<PathBuf>,
+    // last event of each file that wasn't reported yet
+    pending: Vec<Option<Instant>>,
 }
 
 impl FileWatcher {
-    pub fn new(file: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+    pub fn new<P: AsRef<Path>>(files: &[P]) -> anyhow::Result<Self> {
         let (sender, receiver) = std::sync::mpsc::channel();
         let mut watcher =
             notify::recommended_watcher(sender).context("Failed to init file watcher")?;
 
-        watcher
-            .watch(file.as_ref(), notify::RecursiveMode::NonRecursive)
-            .context("Failed to spawn a file watcher")?;
+        let mut dirs = Vec::<(PathBuf, bool)>::new();
+        let mut watched = Vec::with_capacity(files.len());
+        for file in files {
+            let file = file.as_ref();
+            let name = file
+                .file_name()
+                .with_context(|| format!("{file:?} is not a file"))?;
+            let dir = match file.parent() {
+                Some(dir) if dir != Path::new("") => dir,
+                _ => Path::new("."),
+            };
+            let dir = dir
+                .canonicalize()
+                .with_context(|| format!("Failed to watch {dir:?}"))?;
+            if !dirs.iter().any(|(d, _)| *d == dir) {
+                watcher
+                    .watch(&dir, notify::RecursiveMode::NonRecursive)
+                    .context("Failed to spawn a file watcher")?;
+                dirs.push((dir.clone(), true));
+            }
+            watched.push(dir.join(name));
+        }
+
+        Ok(Self {
+            receiver,
+            watcher,
+            dirs,
+            pending: vec![None; watched.len()],
+            files: watched,
+        })
+    }
+
+    /// Indices of the files that were changed and then left alone for a moment,
+    /// errors of the watcher are logged.
+    pub fn changed(&mut self) -> Vec<usize> {
+        while let Ok(event) = self.receiver.try_recv() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    log::error!("File watcher: {e}");
+                    self.dirs.iter_mut().for_each(|(_, armed)| *armed = false);
+                    continue;
+                }
+            };
+            use notify::EventKind;
+            match event.kind {
+                // inotify reports a removed directory with the kind of a file removal
+                EventKind::Remove(_) => {
+                    for (dir, armed) in self.dirs.iter_mut().filter(|(d, _)| !d.exists()) {
+                        if *armed {
+                            log::warn!("{dir:?} was removed, waiting for it");
+                        }
+                        *armed = false;
+                    }
+                }
+                EventKind::Create(_) | EventKind::Modify(_) => {}
+                _ => continue,
+            }
+            for (file, pending) in self.files.iter().zip(&mut self.pending) {
+                if event.paths.contains(file) {
+                    *pending = Some(Instant::now());
+                }
+            }
+        }
+        self.rearm();
+
+        // a file can be missing between the remove and the create of a save
+        let mut changed = Vec::new();
+        for (i, (file, pending)) in self.files.iter().zip(&mut self.pending).enumerate() {
+            if pending.is_some_and(|last| last.elapsed() >= DEBOUNCE) && file.exists() {
+                *pending = None;
+                changed.push(i);
+            }
+        }
+        changed
+    }
 
-        Ok(Self { receiver, watcher })
+    // files of a directory that came back are reported as changed, they likely are
+    fn rearm(&mut self) {
+        for (dir, armed) in self
+            .dirs
+            .iter_mut()
+            .filter(|(d, armed)| !armed && d.exists())
+        {
+            let _ = self.watcher.unwatch(dir);
+            match self.watcher.watch(dir, notify::RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    log::info!("Watching {dir:?} again");
+                    *armed = true;
+                    for (file, pending) in self.files.iter().zip(&mut self.pending) {
+                        if file.parent() == Some(dir) {
+                            *pending = Some(Instant::now());
+                        }
+                    }
+                }
+                Err(e) => log::error!("File watcher: {e}"),
+            }
+        }
     }
 }
 
 pub enum Msg {
     Exit,
-    ExtractData(FrameBuffer),
+    ExtractData {
+        frame: FrameBuffer,
+        // seconds since the recording has started
+        time: f32,
+    },
     SavePng {
         frame: FrameBuffer,
         resolution: PhysicalSize<u32>,
     },
-    SaveMp4 {
+    SaveHdr {
+        frame: FrameBuffer,
+        resolution: PhysicalSize<u32>,
+        target_format: TargetFormat,
+        format: HdrFormat,
+    },
+    SaveVideo {
         rate: u32,
         resolution: PhysicalSize<u32>,
+        format: VideoFormat,
+        // value of `Time` when the recording has started
+        start: f32,
+        soundtrack: Option<Soundtrack>,
+        // named after the time without one
+        path: Option<PathBuf>,
     },
 }
 
@@ -51,9 +183,10 @@ impl std::fmt::Display for Msg {
             "{}",
             match self {
                 Msg::Exit => "Msg::Exit",
-                Msg::ExtractData(_) => "Msg::ExtractData",
+                Msg::ExtractData { .. } => "Msg::ExtractData",
                 Msg::SavePng { .. } => "Msg::SavePng",
-                Msg::SaveMp4 { .. } => "Msg::SaveMp4",
+                Msg::SaveHdr { .. } => "Msg::SaveHdr",
+                Msg::SaveVideo { .. } => "Msg::SaveVideo",
             }
         )
     }
@@ -73,20 +206,20 @@ impl Channel {
         let thread_handle = std::thread::spawn(move || {
             let _ = rt_handle.enter();
             // without wrapping it into Option the tokio runtime will panic
-            // with the "JoinHandle polled after completion" message on Msg::SaveMp4 request
-            let mut tasks = Some(Vec::<tokio::task::JoinHandle<RawFrame>>::new());
+            // with the "JoinHandle polled after completion" message on Msg::SaveVideo request
+            let mut tasks = Some(Vec::<tokio::task::JoinHandle<(f32, RawFrame)>>::new());
 
             while let Ok(msg) = rx.recv() {
                 log::info!("Accepted request {msg}");
                 match msg {
                     Msg::Exit => break,
-                    Msg::ExtractData(frame_buffer) => {
+                    Msg::ExtractData { frame, time } => {
                         if let Some(ref mut ts) = tasks {
                             ts.push(rt_handle.spawn(async move {
-                                frame_buffer.map_read().await;
-                                let frame = frame_buffer.extract_data();
+                                frame.map_read().await;
+                                let frame = frame.extract_data();
                                 log::info!("Frame data is extracted");
-                                frame
+                                (time, frame)
                             }));
                         }
                     }
@@ -102,11 +235,37 @@ impl Channel {
                             }
                         });
                     }
-                    Msg::SaveMp4 { rate, resolution } => {
+                    Msg::SaveHdr {
+                        frame,
+                        resolution,
+                        target_format,
+                        format,
+                    } => {
+                        rt_handle.spawn(async move {
+                            frame.map_read().await;
+                            match crate::capture::save_raw_frame_as_hdr(
+                                &frame.extract_data(),
+                                &resolution,
+                                target_format,
+                                format,
+                            ) {
+                                Ok(file) => log::info!("{file} saved!"),
+                                Err(e) => log::error!("{e}"),
+                            }
+                        });
+                    }
+                    Msg::SaveVideo {
+                        rate,
+                        resolution,
+                        format,
+                        start,
+                        soundtrack,
+                        path,
+                    } => {
                         let frames = rt_handle.block_on(async {
                             let tasks = tasks.take().unwrap();
                             let n_tasks = tasks.len();
-                            let mut frames = Vec::<RawFrame>::with_capacity(n_tasks);
+                            let mut frames = Vec::with_capacity(n_tasks);
                             for frame in tasks.into_iter() {
                                 match frame.await {
                                     Ok(f) => frames.push(f),
@@ -125,12 +284,17 @@ impl Channel {
 
                         // clear the tasks pool
                         tasks = Some(Vec::new());
+                        let frames = crate::capture::resample_frames(frames, rate);
 
                         rt_handle.spawn(async move {
-                            match crate::capture::save_raw_frames_as_mp4(
+                            match crate::capture::save_raw_frames_as_video(
                                 frames,
                                 &resolution,
                                 rate as _,
+                                format,
+                                start,
+                                soundtrack.as_ref(),
+                                path.as_deref(),
                             ) {
                                 Ok(file) => log::info!("{file} saved!"),
                                 Err(e) => log::error!("{e}"),
@@ -171,26 +335,36 @@ impl Drop for Channel {
 }
 
 pub struct Time {
-    pub start: Instant,
     pub delta: f32,
     pub frame_count: u32,
     pub accum_time: f32,
     pub last_frame_inst: Instant,
+    /// Real seconds between the last two frames.
+    pub frame_time: f32,
+    /// Time in seconds that is exposed to shaders, doesn't advance while paused.
+    pub elapsed: f32,
+    pub paused: bool,
 }
 
 impl Time {
     pub fn new() -> Self {
         Self {
-            start: Instant::now(),
             delta: 0.0,
             frame_count: 0,
             accum_time: 0.0,
             last_frame_inst: Instant::now(),
+            frame_time: 0.0,
+            elapsed: 0.0,
+            paused: false,
         }
     }
 
     pub fn update(&mut self) {
-        self.accum_time += self.last_frame_inst.elapsed().as_secs_f32();
+        self.frame_time = self.last_frame_inst.elapsed().as_secs_f32();
+        if !self.paused {
+            self.elapsed += self.frame_time;
+        }
+        self.accum_time += self.frame_time;
         self.last_frame_inst = Instant::now();
         self.frame_count += 1;
         if self.frame_count == 10 {
@@ -200,20 +374,31 @@ impl Time {
             self.frame_count = 0;
         }
     }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        log::info!(
+            "{state} at {t:.2}s",
+            state = if self.paused { "Paused" } else { "Resumed" },
+            t = self.elapsed
+        );
+    }
+
+    pub fn seek(&mut self, offset: f32) {
+        self.elapsed = (self.elapsed + offset).max(0.0);
+        log::info!("Seeked to {t:.2}s", t = self.elapsed);
+    }
 }
 
 #[derive(Clone, Copy)]
 pub struct AllignedBufferSize {
-    pub width: u32,
-    pub height: u32,
     pub buffer_size: u32,
     pub padded_bytes_per_row: u32,
     pub unpadded_bytes_per_row: u32,
 }
 
 impl AllignedBufferSize {
-    pub fn new(width: u32, height: u32) -> Self {
-        let bytes_per_pixel = std::mem::size_of::<u32>() as u32;
+    pub fn new(width: u32, height: u32, bytes_per_pixel: u32) -> Self {
         let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
         let unpadded_bytes_per_row = width * bytes_per_pixel;
         let padding = (align - unpadded_bytes_per_row % align) % align;
@@ -221,8 +406,6 @@ impl AllignedBufferSize {
         let buffer_size = padded_bytes_per_row * height;
 
         Self {
-            width,
-            height,
             buffer_size,
             padded_bytes_per_row,
             unpadded_bytes_per_row,
@@ -230,18 +413,33 @@ impl AllignedBufferSize {
     }
 }
 
-impl From<&PhysicalSize<u32>> for AllignedBufferSize {
-    fn from(size: &PhysicalSize<u32>) -> Self {
-        Self::new(size.width, size.height)
-    }
-}
-
 pub fn current_time_string() -> String {
     Local::now().time().format("%H-%M-%S-%3f").to_string()
 }
 
+// set once the replies of `--ipc -` are written to stdout
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+
+/// Keeps everything but the replies to commands out of stdout, see [`print`].
+pub fn reserve_stdout() {
+    STDOUT_RESERVED.store(true, Ordering::Relaxed);
+}
+
+/// Prints a line for the user, to stderr once stdout is reserved for replies.
+pub fn print(line: impl std::fmt::Display) {
+    if STDOUT_RESERVED.load(Ordering::Relaxed) {
+        eprintln!("{line}");
+    } else {
+        println!("{line}");
+    }
+}
+
+// only on a terminal that isn't reserved for replies
 pub fn clear_screen() {
     let mut stdout = std::io::stdout();
+    if STDOUT_RESERVED.load(Ordering::Relaxed) || !std::io::IsTerminal::is_terminal(&stdout) {
+        return;
+    }
     let _ = crossterm::execute!(stdout, MoveTo(0, 0), Clear(ClearType::All));
     let _ = stdout.flush();
 }
@@ -253,3 +451,25 @@ pub fn create_file_cwd(file_name: &str) -> anyhow::Result<File> {
 
     Ok(file)
 }
+
+/// Waits for a future on the current thread, for wgpu's futures inside the event loop
+/// where the runtime can't be blocked on.
+pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    struct ThreadWaker(std::thread::Thread);
+
+    impl std::task::Wake for ThreadWaker {
+        fn wake(self: std::sync::Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let mut future = std::pin::pin!(future);
+    let waker = std::sync::Arc::new(ThreadWaker(std::thread::current())).into();
+    let mut cx = std::task::Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            std::task::Poll::Ready(output) => return output,
+            std::task::Poll::Pending => std::thread::park(),
+        }
+    }
+}
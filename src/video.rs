@@ -0,0 +1,247 @@
+use crate::utils::RawFrame;
+use anyhow::Context;
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::mpsc,
+};
+
+/// Amount of decoded frames that can wait in the channel before the decoder blocks.
+const FRAME_QUEUE_LEN: usize = 4;
+/// Seeking further than this (in seconds) restarts the decoder instead of skipping frames.
+const MAX_SKIP_SECS: f32 = 2.0;
+
+pub struct Video {
+    pub width: u32,
+    pub height: u32,
+    path: PathBuf,
+    fps: f32,
+    duration: f32,
+    decoder: Option<Decoder>,
+    // index of the last frame that was handed out
+    current_idx: Option<u64>,
+    // index wanted by the previous call, to tell seeks from a decoder that falls behind
+    wanted_idx: Option<u64>,
+    // set when a decoder stopped before its first frame, so it isn't restarted every frame
+    failed: bool,
+}
+
+struct Decoder {
+    process: Child,
+    receiver: mpsc::Receiver<RawFrame>,
+    start_idx: u64,
+    next_idx: u64,
+}
+
+impl Video {
+    #[rustfmt::skip]
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let probe = Command::new("ffprobe")
+            .args([
+                "-v", "error",
+                "-select_streams", "v:0",
+                "-show_entries", "stream=width,height,r_frame_rate:format=duration",
+                "-of", "default=noprint_wrappers=1",
+            ])
+            .arg(path)
+            .output()
+            .context("Failed to spawn ffprobe")?;
+
+        if !probe.status.success() {
+            anyhow::bail!(
+                "{path:?}: {err}",
+                err = String::from_utf8_lossy(&probe.stderr).trim()
+            );
+        }
+
+        let (mut width, mut height, mut fps, mut duration) = (0, 0, 0.0, f32::INFINITY);
+        for line in String::from_utf8_lossy(&probe.stdout).lines() {
+            match line.split_once('=') {
+                Some(("width", v)) => width = v.parse().unwrap_or_default(),
+                Some(("height", v)) => height = v.parse().unwrap_or_default(),
+                Some(("r_frame_rate", v)) => fps = parse_rate(v).unwrap_or_default(),
+                // can be "N/A" for streams without a known length
+                Some(("duration", v)) => duration = v.parse().unwrap_or(f32::INFINITY),
+                _ => {}
+            }
+        }
+
+        if width == 0 || height == 0 || fps <= 0.0 {
+            anyhow::bail!("{path:?}: no video stream found");
+        }
+
+        log::info!("Opened video {path:?}: {width}x{height} at {fps} fps, {duration}s");
+
+        Ok(Self {
+            width,
+            height,
+            path: path.to_path_buf(),
+            fps,
+            duration,
+            decoder: None,
+            current_idx: None,
+            wanted_idx: None,
+            failed: false,
+        })
+    }
+
+    /// Returns the frame that should be visible at `time`,
+    /// or `None` if it is the same frame that was returned last time
+    /// (or it's not decoded yet, unless `block` is set).
+    /// The video is looped, so any positive `time` is valid.
+    /// A decoder that stops (on a corrupt stream) is restarted, unless it didn't get to a frame.
+    pub fn frame_at(&mut self, time: f32, block: bool) -> Option<RawFrame> {
+        if self.failed {
+            return None;
+        }
+        let wanted = (time.max(0.0) * self.fps) as u64;
+        let max_skip = (MAX_SKIP_SECS * self.fps) as u64;
+        // only jumps of `time` restart the decoder when it's behind,
+        // otherwise a slow decoder would be restarted over and over
+        let seeked = self
+            .wanted_idx
+            .is_some_and(|prev| wanted < prev || wanted > prev + max_skip);
+        self.wanted_idx = Some(wanted);
+        if self.current_idx == Some(wanted) {
+            return None;
+        }
+
+        let restart = match self.decoder {
+            Some(ref d) => wanted < d.next_idx || (seeked && wanted > d.next_idx + max_skip),
+            None => true,
+        };
+
+        if restart {
+            match Decoder::spawn(
+                &self.path,
+                wanted,
+                self.fps,
+                self.duration,
+                self.frame_size(),
+            ) {
+                Ok(d) => self.decoder = Some(d),
+                Err(e) => {
+                    log::error!("{e}");
+                    self.decoder = None;
+                    return None;
+                }
+            }
+        }
+
+        let decoder = self.decoder.as_mut()?;
+        let mut frame = None;
+        let mut stopped = false;
+        while decoder.next_idx <= wanted {
+            let next = if block {
+                decoder
+                    .receiver
+                    .recv()
+                    .map_err(|_| mpsc::TryRecvError::Disconnected)
+            } else {
+                decoder.receiver.try_recv()
+            };
+            match next {
+                Ok(f) => {
+                    frame = Some(f);
+                    decoder.next_idx += 1;
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                // ffmpeg exited, its own error is on stderr
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    stopped = true;
+                    break;
+                }
+            }
+        }
+
+        if frame.is_some() {
+            self.current_idx = Some(decoder.next_idx - 1);
+        }
+        if stopped {
+            if decoder.next_idx > decoder.start_idx {
+                log::error!("Decoding {:?} stopped, restarting it", self.path);
+            } else {
+                log::error!(
+                    "Decoding {:?} stopped before the first frame, the channel isn't updated anymore",
+                    self.path
+                );
+                self.failed = true;
+            }
+            self.decoder = None;
+        }
+
+        frame
+    }
+
+    fn frame_size(&self) -> usize {
+        self.width as usize * self.height as usize * 4
+    }
+}
+
+impl Decoder {
+    #[rustfmt::skip]
+    fn spawn(
+        path: &Path,
+        start_idx: u64,
+        fps: f32,
+        duration: f32,
+        frame_size: usize,
+    ) -> anyhow::Result<Self> {
+        let start = format!("{}", (start_idx as f32 / fps) % duration);
+        let mut process = Command::new("ffmpeg")
+            .args([
+                "-v", "error",
+                "-nostdin",
+                // start decoding from the requested position
+                "-ss", &start,
+                // loop the input forever
+                "-stream_loop", "-1",
+            ])
+            .arg("-i")
+            .arg(path)
+            .args([
+                // decode into raw rgba frames written to stdout
+                "-f", "rawvideo",
+                "-pix_fmt", "rgba",
+                "-an",
+                "-",
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to spawn ffmpeg")?;
+
+        let mut stdout = process.stdout.take()
+            .context("Failed to get ffmpeg's stdout")?;
+        let (sender, receiver) = mpsc::sync_channel(FRAME_QUEUE_LEN);
+        std::thread::spawn(move || loop {
+            let mut frame = vec![0; frame_size];
+            if stdout.read_exact(&mut frame).is_err() || sender.send(frame).is_err() {
+                break;
+            }
+        });
+
+        Ok(Self {
+            process,
+            receiver,
+            start_idx,
+            next_idx: start_idx,
+        })
+    }
+}
+
+impl Drop for Decoder {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+// ffprobe reports frame rates as a fraction, e.g. "30000/1001"
+fn parse_rate(rate: &str) -> Option<f32> {
+    let (num, den) = rate.split_once('/')?;
+    let (num, den) = (num.parse::<f32>().ok()?, den.parse::<f32>().ok()?);
+    (den != 0.0).then(|| num / den)
+}